@@ -10,6 +10,12 @@ let showRelative = false;
 
 const commits = {};
 
+// Only present when the site was built with `--content-addressed`, mapping
+// each sha to the file holding its data.
+const index = fetch('index.json')
+  .then(r => r.ok ? r.json() : {})
+  .catch(() => ({}));
+
 async function run() {
   const relative = document.getElementById('relative');
   showRelative = relative.checked;
@@ -58,7 +64,8 @@ async function run() {
 async function loadCommit(commit) {
   try {
     console.log('loading', commit);
-    const response = await fetch(commit + '.json');
+    const file = (await index)[commit] || commit + '.json';
+    const response = await fetch(file);
     const json = await response.json();
     json.sha = commit;
     commits[commit] = json;
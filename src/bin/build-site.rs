@@ -1,38 +1,239 @@
-use failure::Error;
-use shared::{Commit, GitCommit};
-use std::collections::BTreeMap;
-use std::env;
+use failure::{bail, Error};
+use shared::{Commit, GitCommit, LogFormat};
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
-use std::io::Read;
+use std::io::Write;
 use std::path::{Path, PathBuf};
-use std::process::{self, Command};
+use std::process;
 
-const USAGE: &'static str = "
+const USAGE: &str = "
 This is some usage
 
 Usage:
-    build-site <rust-repo> <cache-dir> <out-dir>
+    build-site [options] bisect <rust-repo> <cache-dir> <job> <step> <good-sha> <bad-sha>
+    build-site [options] list-jobs <rust-repo> <cache-dir>
+    build-site [options] top-movers [--count <n>] <rust-repo> <cache-dir>
+    build-site [options] export <rust-repo> <cache-dir> <since> <until> <file>
+    build-site [options] import <file> <cache-dir>
+    build-site [options] [--exclude-crate <glob>]... rebuild-overall <cache-dir> <out-dir>
+    build-site [options] [--exclude-commit <sha>]... [--exclude-crate <glob>]... --from-manifest <cache-dir> <out-dir>
+    build-site [options] [--exclude-commit <sha>]... [--exclude-crate <glob>]... <rust-repo> <cache-dir> <out-dir>
     build-site -h | --help
 
+The `bisect` command prints the duration of a job's step for every cached
+commit from <good-sha> to <bad-sha>, pointing out the largest jump.
+
+The `list-jobs` command prints the name of every job in the charted commits
+along with how many of them it appears in.
+
+The `top-movers` command prints the job steps whose duration changed the most
+between the latest commit and the one before it, both slower and faster.
+
+The `export` command writes the data of every commit between the <since> and
+<until> dates (inclusive, `YYYY-MM-DD` or RFC 3339) into a single archive,
+which `import` unpacks back into a cache directory.
+
+The `rebuild-overall` command writes just `overall.json` from every commit in
+the cache, ordered by when its build finished, without needing git at all.
+
 Options:
     -h --help                    Show this screen.
+    --bundle                     Write all output into a single `data.json`
+    --s3-endpoint <url>          Custom endpoint for S3-compatible stores
+    --log-format <fmt>           Log output format, `human` or `json`
+                                 [default: human]
+    --interpolate-missing        Fill in jobs missing from a commit using the
+                                 neighboring commits rather than charting zero
+    --order <order>              Order of commits in the output, `asc` for
+                                 oldest-first or `desc` for newest-first
+                                 [default: asc]
+    --validate                   Check the generated data is consistent and
+                                 fail if it isn't
+    --baseline <sha>             Also emit each point's percentage change
+                                 relative to this commit
+    --regression-threshold <pct>  With `--baseline`, list each step which is
+                                 slower than in the baseline by more than this
+                                 percentage in `regressions.json`, along with
+                                 the crates which grew the most [default: 10]
+    --badge-out <file>           Write the total CI minutes of the most recent
+                                 commit to this file, as a shields.io endpoint
+                                 if it ends in `.json` or plain text otherwise
+    --pretty                     Pretty-print the generated JSON
+    --aggregate-runs <how>       For commits built more than once, chart the
+                                 `min` or `median` over all of the runs rather
+                                 than just the first
+    --decay-half-life <n>        Rank the slowest jobs weighting recent commits
+                                 more, halving the weight every <n> commits,
+                                 and add the `weighted_average` they're ranked
+                                 by to `ranking.json`
+    --order-by <how>             Order commits by their `date` or `topo`logically
+                                 by their position in the first-parent history
+                                 [default: date]
+    --timestamped                Write into a new timestamped directory within
+                                 <out-dir>, pointing the `latest` symlink at it
+    --force-refresh <shas>       Re-download the given comma-separated commits,
+                                 or `all` of them, even if they're cached
+    --exclude-commit <sha>       Leave this commit out of the site, may be given
+                                 more than once
+    --exclude-crate <glob>       Leave crates matching this glob, such as build
+                                 scripts, out of the per-crate totals, may be
+                                 given more than once
+    --keep-excluded-crates       Still count the time of excluded crates, all
+                                 together as `excluded`
+    --since <date>               Only chart commits from this date on, rather
+                                 than the most recent 100
+    --until <date>               Only chart commits up to this date
+    --channel <channel>          Only include commits on this release channel,
+                                 `nightly`, `beta` or `stable`
+    --branch-from-subject <re>   Take each commit's channel from its subject
+                                 instead, as the regex's first capture group
+                                 (or whole match), for repos without
+                                 `src/ci/channel`
+    --min-commits <n>            Skip statistics over all commits, such as the
+                                 job ranking and regressions, when fewer than
+                                 <n> commits are charted [default: 0]
+    --rich-metadata              Include each commit's PR number, subject and
+                                 PR author in `overall.json`
+    --from-manifest              Take the commits from the manifest published by
+                                 `publish-data-to-s3` rather than a git checkout
+    --cost-rates <file>          Also write `cost.json`, each job's cost from a
+                                 JSON list of `{\"jobs\": <glob>, \"per_hour\": <rate>}`
+                                 where the first matching glob applies and
+                                 unmatched jobs cost nothing
+    --incidents <file>           Tag each commit in `overall.json` with the
+                                 known CI incidents it was built during, from a
+                                 JSON list of `{\"start\", \"end\", \"description\"}`
+    --rolling-days <n>           Also chart the average total CI time of the
+                                 commits over the <n> days up to each one
+    --count <n>                  Number of steps `top-movers` prints
+                                 [default: 10]
+    --content-addressed          Name each commit's file after a hash of its
+                                 contents, listed in `index.json` by sha
 ";
 
 #[derive(Debug, serde::Deserialize)]
 struct Args {
+    cmd_bisect: bool,
+    cmd_list_jobs: bool,
+    cmd_top_movers: bool,
+    cmd_export: bool,
+    cmd_import: bool,
+    cmd_rebuild_overall: bool,
     arg_rust_repo: PathBuf,
     arg_cache_dir: PathBuf,
     arg_out_dir: PathBuf,
+    arg_job: String,
+    arg_step: String,
+    arg_good_sha: String,
+    arg_bad_sha: String,
+    arg_since: String,
+    arg_until: String,
+    arg_file: PathBuf,
+    flag_bundle: bool,
+    flag_s3_endpoint: Option<String>,
+    flag_log_format: LogFormat,
+    flag_interpolate_missing: bool,
+    flag_order: Order,
+    flag_validate: bool,
+    flag_baseline: Option<String>,
+    flag_regression_threshold: f64,
+    flag_badge_out: Option<PathBuf>,
+    flag_decay_half_life: Option<f64>,
+    flag_pretty: bool,
+    flag_aggregate_runs: Option<Aggregate>,
+    flag_content_addressed: bool,
+    flag_order_by: OrderBy,
+    flag_timestamped: bool,
+    flag_force_refresh: Option<String>,
+    flag_exclude_commit: Vec<String>,
+    flag_channel: Option<String>,
+    flag_min_commits: usize,
+    flag_branch_from_subject: Option<String>,
+    flag_rich_metadata: bool,
+    flag_from_manifest: bool,
+    flag_count: usize,
+    flag_cost_rates: Option<PathBuf>,
+    flag_incidents: Option<PathBuf>,
+    flag_rolling_days: Option<u32>,
+    flag_exclude_crate: Vec<String>,
+    flag_keep_excluded_crates: bool,
+    flag_since: Option<String>,
+    flag_until: Option<String>,
 }
 
-fn main() {
-    env_logger::init();
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum OrderBy {
+    Date,
+    Topo,
+}
 
-    let args: Args = docopt::Docopt::new(USAGE)
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum Aggregate {
+    Min,
+    Median,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Order {
+    Asc,
+    Desc,
+}
+
+/// Destination for all the files generated by the site.
+trait OutputSink {
+    /// Writes the file `name`, a path relative to the root of the output.
+    fn write_file(&mut self, name: &str, contents: Vec<u8>) -> Result<(), Error>;
+
+    /// Whether JSON written to this sink is pretty-printed.
+    fn pretty(&self) -> bool;
+
+    /// Called once everything has been written.
+    fn finish(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Writes each file out individually into a directory.
+struct DirSink {
+    dir: PathBuf,
+    pretty: bool,
+}
+
+/// Collects every file into one `data.json` keyed by file name, for
+/// `--bundle`.
+struct BundleSink {
+    dir: PathBuf,
+    pretty: bool,
+    files: BTreeMap<String, serde_json::Value>,
+}
+
+fn main() {
+    let mut args: Args = docopt::Docopt::new(USAGE)
         .and_then(|d| d.deserialize())
         .unwrap_or_else(|e| e.exit());
+    shared::init_logger(args.flag_log_format);
 
-    let err = match run(&args) {
+    let result = if let Err(e) = resolve_args(&mut args) {
+        Err(e)
+    } else if args.cmd_bisect {
+        bisect(&args)
+    } else if args.cmd_list_jobs {
+        list_jobs(&args)
+    } else if args.cmd_top_movers {
+        top_movers(&args)
+    } else if args.cmd_export {
+        export(&args)
+    } else if args.cmd_import {
+        import(&args)
+    } else if args.cmd_rebuild_overall {
+        rebuild_overall(&args)
+    } else {
+        run(&args)
+    };
+    let err = match result {
         Ok(()) => return,
         Err(e) => e,
     };
@@ -43,28 +244,618 @@ fn main() {
     process::exit(1);
 }
 
+/// Expands the possibly-abbreviated SHAs given on the command line to full
+/// ones up front, so that an ambiguous one is an error rather than quietly
+/// matching several commits.
+///
+/// They're looked up amongst the published commits with `--from-manifest`,
+/// otherwise in the git history if there is one or else in the cache.
+fn resolve_args(args: &mut Args) -> Result<(), Error> {
+    let mut published = None;
+    let mut resolve = |args: &Args, sha: &str| -> Result<String, Error> {
+        if sha.len() == 40 {
+            return Ok(sha.to_string());
+        }
+        if !args.flag_from_manifest {
+            return if args.arg_rust_repo.as_os_str().is_empty() {
+                shared::resolve_sha(&args.arg_cache_dir, sha)
+            } else {
+                shared::resolve_git_sha(&args.arg_rust_repo, sha)
+            };
+        }
+        if published.is_none() {
+            let s3_url = shared::s3_url(args.flag_s3_endpoint.as_deref());
+            published = Some(shared::download_manifest(&s3_url)?.unwrap_or_default());
+        }
+        let matches = published
+            .iter()
+            .flatten()
+            .filter(|entry| entry.sha.starts_with(sha))
+            .collect::<Vec<_>>();
+        match matches[..] {
+            [entry] => Ok(entry.sha.clone()),
+            [] => bail!("no published commit matches `{}`", sha),
+            [a, b, ..] => bail!("`{}` is ambiguous, matching {} and {}", sha, a.sha, b.sha),
+        }
+    };
+
+    let mut excluded = std::mem::take(&mut args.flag_exclude_commit);
+    for sha in excluded.iter_mut() {
+        *sha = resolve(args, sha)?;
+    }
+    args.flag_exclude_commit = excluded;
+    if let Some(list) = args.flag_force_refresh.clone() {
+        if list != "all" {
+            let shas = list
+                .split(',')
+                .filter(|sha| !sha.is_empty())
+                .map(|sha| resolve(args, sha))
+                .collect::<Result<Vec<_>, _>>()?;
+            args.flag_force_refresh = Some(shas.join(","));
+        }
+    }
+    if let Some(sha) = args.flag_baseline.clone() {
+        args.flag_baseline = Some(resolve(args, &sha)?);
+    }
+    if args.cmd_bisect {
+        args.arg_good_sha = resolve(args, &args.arg_good_sha)?;
+        args.arg_bad_sha = resolve(args, &args.arg_bad_sha)?;
+    }
+    Ok(())
+}
+
 fn run(args: &Args) -> Result<(), Error> {
-    let commits = get_commits(&args.arg_rust_repo, &args.arg_cache_dir)?;
+    let mut commits = get_commits(args)?;
+    if let Some(channel) = &args.flag_channel {
+        commits.retain(|(git, _)| git.channel.as_ref() == Some(channel));
+    }
+    commits.retain(|(git, _)| {
+        let excluded = args.flag_exclude_commit.contains(&git.sha);
+        if excluded {
+            log::debug!("excluding {}", git.sha);
+        }
+        !excluded
+    });
+
+    let out_dir = if args.flag_timestamped {
+        let name = chrono::Utc::now().format("%Y-%m-%dT%H%M%SZ").to_string();
+        args.arg_out_dir.join(name)
+    } else {
+        args.arg_out_dir.clone()
+    };
+    if !out_dir.exists() {
+        std::fs::create_dir_all(&out_dir)?;
+    }
+    let mut output: Box<dyn OutputSink> = if args.flag_bundle {
+        Box::new(BundleSink {
+            dir: out_dir.clone(),
+            pretty: args.flag_pretty,
+            files: BTreeMap::new(),
+        })
+    } else {
+        Box::new(DirSink {
+            dir: out_dir.clone(),
+            pretty: args.flag_pretty,
+        })
+    };
+    let baseline_sha = args.flag_baseline.as_deref();
+    let baseline = baseline_sha.and_then(|sha| {
+        commits
+            .iter()
+            .find(|(git, _)| git.sha == sha)
+            .map(|(_, commit)| commit)
+    });
+    // The baseline may well be older than the commits we're charting, in
+    // which case it's loaded on its own.
+    let loaded_baseline = match baseline_sha {
+        Some(sha) if baseline.is_none() => load_commits(
+            &args.arg_cache_dir,
+            &[sha],
+            args.flag_s3_endpoint.as_deref(),
+            args.flag_force_refresh.as_deref(),
+        )?
+        .pop(),
+        _ => None,
+    };
+    let baseline = baseline.or(loaded_baseline.as_ref());
+    build(args, &commits, baseline_sha.zip(baseline), &mut *output)?;
+    output.finish()?;
+    if args.flag_timestamped {
+        update_latest(&out_dir)?;
+    }
+    if let Some(path) = &args.flag_badge_out {
+        write_badge(&commits, path)?;
+    }
+    Ok(())
+}
 
-    if !args.arg_out_dir.exists() {
-        std::fs::create_dir_all(&args.arg_out_dir)?;
+/// Writes all of the site's data for `commits` (newest first) into `output`,
+/// compared against `baseline` if there is one.
+fn build(
+    args: &Args,
+    commits: &[(GitCommit, Commit)],
+    baseline: Option<(&str, &Commit)>,
+    output: &mut dyn OutputSink,
+) -> Result<(), Error> {
+    write_overall(args, commits, baseline.map(|(_, c)| c), output)?;
+    write_graph(commits, output)?;
+    write_reliability(commits, output)?;
+    if let Some((_, baseline)) = baseline {
+        // Like the ranking, regressions over a handful of commits are noise
+        if commits.len() < args.flag_min_commits {
+            println!(
+                "only {} commits available, fewer than --min-commits {}, \
+                 skipping regressions.json",
+                commits.len(),
+                args.flag_min_commits
+            );
+        } else {
+            write_regressions(commits, baseline, args.flag_regression_threshold, output)?;
+        }
+    }
+    if let Some(path) = &args.flag_cost_rates {
+        let rates: Vec<CostRate> = serde_json::from_slice(&fs::read(path)?)?;
+        write_cost(commits, &rates, output)?;
+    }
+    write_config(args, baseline.map(|(sha, _)| sha), output)?;
+    write_each_commit(commits, args.flag_content_addressed, output)?;
+    Ok(())
+}
+
+fn rebuild_overall(args: &Args) -> Result<(), Error> {
+    let mut shas = Vec::new();
+    for entry in fs::read_dir(args.arg_cache_dir.join("commits"))? {
+        let name = entry?.file_name().to_string_lossy().into_owned();
+        if let Some(sha) = name.strip_suffix(".json.gz") {
+            shas.push(sha.to_string());
+        }
+    }
+    let data = load_commits(
+        &args.arg_cache_dir,
+        &shas.iter().map(|s| s.as_str()).collect::<Vec<_>>(),
+        args.flag_s3_endpoint.as_deref(),
+        None,
+    )?;
+
+    // Without git the build's own times are all there is to order by
+    let mut commits = Vec::new();
+    for (sha, commit) in shas.into_iter().zip(data) {
+        let raw = commit
+            .finish_time
+            .clone()
+            .or_else(|| commit.start_time.clone())
+            .or_else(|| commit.queue_time.clone())
+            .unwrap_or_default();
+        let entry = shared::ManifestEntry {
+            sha,
+            date: raw,
+            subject: String::new(),
+        };
+        let git = entry.git_commit();
+        if git.date.is_none() {
+            println!("skipping {}, it doesn't record when it was built", git.sha);
+            continue;
+        }
+        commits.push((git, commit));
+    }
+    // Newest first, like `git log`
+    commits.sort_by_key(|(git, _)| std::cmp::Reverse(git.date));
+
+    fs::create_dir_all(&args.arg_out_dir)?;
+    let mut output = DirSink {
+        dir: args.arg_out_dir.clone(),
+        pretty: args.flag_pretty,
+    };
+    write_overall(args, &commits, None, &mut output)
+}
+
+fn list_jobs(args: &Args) -> Result<(), Error> {
+    let commits = get_commits(args)?;
+    let mut counts = BTreeMap::new();
+    for (_git, commit) in commits.iter() {
+        for name in commit.jobs.keys() {
+            *counts.entry(name.as_str()).or_insert(0) += 1;
+        }
+    }
+    for (name, count) in counts {
+        println!("{:<40} {:>4} of {} commits", name, count, commits.len());
+    }
+    Ok(())
+}
+
+/// A job step whose duration changed between two commits.
+struct Mover<'a> {
+    job: &'a str,
+    step: &'a str,
+    delta: f64,
+    pct: f64,
+}
+
+fn top_movers(args: &Args) -> Result<(), Error> {
+    let commits = get_commits(args)?;
+    let (latest, prev) = match &commits[..] {
+        [latest, prev, ..] => (latest, prev),
+        _ => bail!("need at least two commits to compare"),
+    };
+    println!("{:.10} -> {:.10}", prev.0.sha, latest.0.sha);
+    for m in movers(&prev.1, &latest.1).iter().take(args.flag_count) {
+        println!(
+            "{:<30} {:<40} {:+10.2}s {:+8.2}%",
+            m.job, m.step, m.delta, m.pct
+        );
+    }
+    Ok(())
+}
+
+/// Every step run by both commits, ordered by how much its duration changed
+/// in either direction.
+fn movers<'a>(prev: &'a Commit, latest: &'a Commit) -> Vec<Mover<'a>> {
+    let mut ret = Vec::new();
+    for (job, data) in latest.jobs.iter() {
+        let before = match prev.jobs.get(job) {
+            Some(before) => before,
+            None => continue,
+        };
+        for (step, timing) in data.timings.iter() {
+            let old = match before.timings.get(step) {
+                Some(old) => old.dur,
+                None => continue,
+            };
+            let delta = timing.dur - old;
+            if delta == 0.0 {
+                continue;
+            }
+            let pct = if old == 0.0 { 0.0 } else { delta / old * 100.0 };
+            ret.push(Mover {
+                job,
+                step,
+                delta,
+                pct,
+            });
+        }
+    }
+    ret.sort_by(|a, b| b.delta.abs().total_cmp(&a.delta.abs()));
+    ret
+}
+
+/// Version of the archive format written by `export`, bumped on any
+/// incompatible change.
+const ARCHIVE_SCHEMA_VERSION: u32 = 1;
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Archive {
+    schema_version: u32,
+    exported_at: String,
+    commits: Vec<ArchivedCommit>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct ArchivedCommit {
+    sha: String,
+    date: String,
+    data: Commit,
+}
+
+fn export(args: &Args) -> Result<(), Error> {
+    let since = parse_date(&args.arg_since, false)?;
+    let until = parse_date(&args.arg_until, true)?;
+    let mut range = Vec::new();
+    let mut undated = 0;
+    for commit in shared::get_git_commits(&args.arg_rust_repo, Some("bors"))? {
+        let commit = commit?;
+        let date = match commit.date {
+            Some(date) => date,
+            None => {
+                undated += 1;
+                continue;
+            }
+        };
+        // Commits are newest-first
+        if date < since {
+            break;
+        }
+        if date <= until {
+            range.push(commit);
+        }
+    }
+    if undated > 0 {
+        log::warn!("left out {} commits whose date couldn't be parsed", undated);
+    }
+    let shas = range.iter().map(|c| c.sha.as_str()).collect::<Vec<_>>();
+    let data = load_commits(
+        &args.arg_cache_dir,
+        &shas,
+        args.flag_s3_endpoint.as_deref(),
+        args.flag_force_refresh.as_deref(),
+    )?;
+    let archive = Archive {
+        schema_version: ARCHIVE_SCHEMA_VERSION,
+        exported_at: chrono::Utc::now().to_rfc3339(),
+        commits: range
+            .into_iter()
+            .zip(data)
+            .map(|(git, data)| ArchivedCommit {
+                sha: git.sha,
+                date: git.date_raw,
+                data,
+            })
+            .collect(),
+    };
+    write_gz(&args.arg_file, &archive)?;
+    println!(
+        "exported {} commits to {:?}",
+        archive.commits.len(),
+        args.arg_file
+    );
+    Ok(())
+}
+
+fn import(args: &Args) -> Result<(), Error> {
+    #[derive(serde::Deserialize)]
+    struct Header {
+        schema_version: Option<u32>,
+    }
+    let read = || -> Result<_, Error> {
+        let file = std::io::BufReader::new(fs::File::open(&args.arg_file)?);
+        Ok(flate2::read::GzDecoder::new(file))
+    };
+    // Check the version on its own first so an archive in another format is
+    // reported as such rather than as whichever field failed to deserialize.
+    let header: Header = serde_json::from_reader(read()?)?;
+    if header.schema_version != Some(ARCHIVE_SCHEMA_VERSION) {
+        bail!(
+            "unsupported archive schema version {:?}, expected {}",
+            header.schema_version,
+            ARCHIVE_SCHEMA_VERSION
+        );
+    }
+    let archive: Archive = serde_json::from_reader(read()?)?;
+    let dir = args.arg_cache_dir.join("commits");
+    fs::create_dir_all(&dir)?;
+    let mut imported = 0;
+    for commit in archive.commits.iter() {
+        let path = dir.join(&commit.sha).with_extension("json.gz");
+        if path.exists() {
+            log::debug!("{} is already cached", commit.sha);
+            continue;
+        }
+        write_gz(&path, &commit.data)?;
+        imported += 1;
     }
-    write_overall(&commits, &args.arg_out_dir)?;
-    write_each_commit(&commits, &args.arg_out_dir)?;
+    println!(
+        "imported {} of {} commits exported at {}",
+        imported,
+        archive.commits.len(),
+        archive.exported_at
+    );
     Ok(())
 }
 
-fn write_overall(commits: &[(GitCommit, Commit)], out_dir: &Path) -> Result<(), Error> {
+/// Writes `data` as gzipped JSON to `path`.
+///
+/// This goes through a temporary file which is renamed into place, so an
+/// interrupted write never leaves a truncated file which looks complete.
+fn write_gz(path: &Path, data: &impl serde::Serialize) -> Result<(), Error> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    let file = std::io::BufWriter::new(fs::File::create(&tmp)?);
+    let mut gz = flate2::write::GzEncoder::new(file, flate2::Compression::best());
+    serde_json::to_writer(&mut gz, data)?;
+    gz.finish()?.flush()?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Parses a `--since`-style date, either RFC 3339 or a plain `YYYY-MM-DD`
+/// which covers that whole day, so as the `end` of a range it means the end
+/// of the day.
+fn parse_date(s: &str, end: bool) -> Result<chrono::DateTime<chrono::FixedOffset>, Error> {
+    if let Ok(date) = chrono::DateTime::parse_from_rfc3339(s) {
+        return Ok(date);
+    }
+    let day = match chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        Ok(day) => day,
+        Err(_) => bail!("invalid date `{}`, expected `YYYY-MM-DD` or RFC 3339", s),
+    };
+    let time = if end {
+        day.and_hms_opt(23, 59, 59).unwrap()
+    } else {
+        day.and_hms_opt(0, 0, 0).unwrap()
+    };
+    Ok(chrono::DateTime::from_naive_utc_and_offset(
+        time,
+        chrono::FixedOffset::east_opt(0).unwrap(),
+    ))
+}
+
+fn bisect(args: &Args) -> Result<(), Error> {
+    // Walk from the bad commit back to the good one, git giving us the range
+    // newest-first.
+    let mut range = Vec::new();
+    for commit in shared::get_git_commits(&args.arg_rust_repo, Some("bors"))? {
+        let commit = commit?;
+        if range.is_empty() && commit.sha != args.arg_bad_sha {
+            continue;
+        }
+        let good = commit.sha == args.arg_good_sha;
+        range.push(commit);
+        if good {
+            break;
+        }
+    }
+    match range.last() {
+        Some(last) if last.sha == args.arg_good_sha => {}
+        Some(_) => bail!(
+            "`{}` isn't an ancestor of `{}`",
+            args.arg_good_sha,
+            args.arg_bad_sha
+        ),
+        None => bail!("failed to find `{}` in the history", args.arg_bad_sha),
+    }
+    range.reverse();
+
+    let commits_dir = args.arg_cache_dir.join("commits");
+    let mut points = Vec::new();
+    for git in range.iter() {
+        let path = commits_dir.join(&git.sha).with_extension("json.gz");
+        if !path.exists() {
+            println!("{:.10}  not cached", git.sha);
+            continue;
+        }
+        let commit = shared::decode_commit(fs::File::open(&path)?)?;
+        let dur = commit
+            .jobs
+            .get(&args.arg_job)
+            .and_then(|job| job.timings.get(&args.arg_step))
+            .map(|timing| timing.dur);
+        match dur {
+            Some(dur) => points.push((git, dur)),
+            None => println!("{:.10}  no data", git.sha),
+        }
+    }
+    if points.is_empty() {
+        bail!(
+            "no cached commits in the range have `{}` in `{}`",
+            args.arg_step,
+            args.arg_job
+        );
+    }
+
+    // The jump into each point from the previous one with data, either way
+    let largest = (1..points.len()).max_by(|&a, &b| {
+        let jump = |i: usize| points[i].1 - points[i - 1].1;
+        jump(a).abs().total_cmp(&jump(b).abs())
+    });
+    for (i, (git, dur)) in points.iter().enumerate() {
+        let delta = if i == 0 { 0.0 } else { dur - points[i - 1].1 };
+        let marker = if Some(i) == largest {
+            "  <- largest jump"
+        } else {
+            ""
+        };
+        println!("{:.10}  {:10.2}s  {:+10.2}s{}", git.sha, dur, delta, marker);
+    }
+    Ok(())
+}
+
+/// Points the `latest` symlink next to `dir` at it.
+///
+/// The new link is created alongside and renamed over the old one so there's
+/// never a moment without a `latest`.
+#[cfg(unix)]
+fn update_latest(dir: &Path) -> Result<(), Error> {
+    let parent = dir.parent().unwrap();
+    let tmp = parent.join("latest.tmp");
+    if fs::symlink_metadata(&tmp).is_ok() {
+        fs::remove_file(&tmp)?;
+    }
+    std::os::unix::fs::symlink(dir.file_name().unwrap(), &tmp)?;
+    fs::rename(&tmp, parent.join("latest"))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn update_latest(_dir: &Path) -> Result<(), Error> {
+    bail!("`--timestamped` is only supported on unix")
+}
+
+fn write_badge(commits: &[(GitCommit, Commit)], path: &Path) -> Result<(), Error> {
+    // Commits are newest-first
+    let (_git, latest) = match commits.first() {
+        Some(commit) => commit,
+        None => bail!("no commits to generate a badge from"),
+    };
+    let minutes = commit_total(latest) / 60.0;
+    let contents = if path.extension() == Some("json".as_ref()) {
+        serde_json::json!({
+            "schemaVersion": 1,
+            "label": "CI time",
+            "message": format!("{:.0} min", minutes),
+            "color": "blue",
+        })
+        .to_string()
+    } else {
+        format!("{:.0}\n", minutes)
+    };
+    fs::write(path, contents)?;
+    Ok(())
+}
+
+impl dyn OutputSink + '_ {
+    fn write(&mut self, name: &str, data: &impl serde::Serialize) -> Result<(), Error> {
+        let json = self.to_json(data)?;
+        self.write_file(name, json)
+    }
+
+    fn to_json(&self, data: &impl serde::Serialize) -> Result<Vec<u8>, Error> {
+        to_json(data, self.pretty())
+    }
+}
+
+fn to_json(data: &impl serde::Serialize, pretty: bool) -> Result<Vec<u8>, Error> {
+    if pretty {
+        Ok(serde_json::to_vec_pretty(data)?)
+    } else {
+        Ok(serde_json::to_vec(data)?)
+    }
+}
+
+impl OutputSink for DirSink {
+    fn write_file(&mut self, name: &str, contents: Vec<u8>) -> Result<(), Error> {
+        let path = self.dir.join(name);
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(path, contents)?;
+        Ok(())
+    }
+
+    fn pretty(&self) -> bool {
+        self.pretty
+    }
+}
+
+impl OutputSink for BundleSink {
+    fn write_file(&mut self, name: &str, contents: Vec<u8>) -> Result<(), Error> {
+        let value = serde_json::from_slice(&contents)?;
+        self.files.insert(name.to_string(), value);
+        Ok(())
+    }
+
+    fn pretty(&self) -> bool {
+        self.pretty
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        let json = to_json(&self.files, self.pretty)?;
+        fs::write(self.dir.join("data.json"), json)?;
+        Ok(())
+    }
+}
+
+fn write_overall(
+    args: &Args,
+    commits: &[(GitCommit, Commit)],
+    baseline: Option<&Commit>,
+    output: &mut dyn OutputSink,
+) -> Result<(), Error> {
+    // For each job, tracks (count, total, sum of weights, weighted total)
     let mut jobs = BTreeMap::new();
-    for (_sha, commit) in commits.iter() {
+    for (age, (_sha, commit)) in commits.iter().enumerate() {
+        // Commits are newest-first so `age` is how many commits ago this was
+        let weight = match args.flag_decay_half_life {
+            Some(half_life) => 0.5f64.powf(age as f64 / half_life),
+            None => 1.0,
+        };
         for (name, data) in commit.jobs.iter() {
-            let (count, total) = jobs.entry(name).or_insert((0, 0.0));
+            let (count, total, weights, weighted) = jobs.entry(name).or_insert((0, 0.0, 0.0, 0.0));
             *count += 1;
+            *weights += weight;
             for (_name, timing) in data.timings.iter() {
                 // Similar to below, ignore "Distcheck" for total time because
                 // it double-counts a bunch of the smaller steps
                 if name != "Distcheck" {
                     *total += timing.dur;
+                    *weighted += timing.dur * weight;
                 }
             }
         }
@@ -72,57 +863,181 @@ fn write_overall(commits: &[(GitCommit, Commit)], out_dir: &Path) -> Result<(),
 
     let mut slowest_jobs = jobs.keys().cloned().collect::<Vec<_>>();
     slowest_jobs.sort_by_key(|name| {
-        let (count, total) = jobs[name];
-        (-total / (count as f64)) as i64
+        let (_count, _total, weights, weighted) = jobs[name];
+        (-weighted / weights) as i64
     });
 
+    #[derive(serde::Serialize)]
+    struct Rank<'a> {
+        name: &'a str,
+        average: f64,
+        /// With `--decay-half-life`, the average the ranking is by
+        #[serde(skip_serializing_if = "Option::is_none")]
+        weighted_average: Option<f64>,
+        count: usize,
+    }
+    // Averages over a handful of commits are mostly noise, so on a cold
+    // cache only the raw series are written.
+    if commits.len() < args.flag_min_commits {
+        println!(
+            "only {} commits available, fewer than --min-commits {}, \
+             skipping ranking.json",
+            commits.len(),
+            args.flag_min_commits
+        );
+    } else {
+        let ranking = slowest_jobs
+            .iter()
+            .map(|name| {
+                let (count, total, weights, weighted) = jobs[name];
+                Rank {
+                    name,
+                    average: total / (count as f64),
+                    weighted_average: args.flag_decay_half_life.map(|_| weighted / weights),
+                    count,
+                }
+            })
+            .collect::<Vec<_>>();
+        output.write("ranking.json", &ranking)?;
+    }
+
     #[derive(serde::Serialize, Default)]
     struct Data<'a> {
         commits: Vec<Commit<'a>>,
         series: Vec<Series<'a>>,
+        /// The `--rolling-days` average of the total time of all jobs, one
+        /// point per commit
+        #[serde(skip_serializing_if = "Option::is_none")]
+        rolling_average: Option<Vec<f64>>,
     }
     #[derive(serde::Serialize)]
     struct Series<'a> {
         name: &'a str,
         data: Vec<f64>,
+        /// Indices into `data` which were interpolated rather than measured
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        interpolated: Vec<usize>,
+        /// Percentage change of each point relative to the `--baseline`
+        #[serde(skip_serializing_if = "Option::is_none")]
+        vs_baseline: Option<Vec<f64>>,
     }
     #[derive(serde::Serialize)]
     struct Commit<'a> {
         sha: &'a str,
         date: &'a str,
         jobs: BTreeMap<&'a str, Job<'a>>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        approved_by: Option<&'a str>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        channel: Option<&'a str>,
+        /// Only with `--rich-metadata`, to keep the payload small otherwise
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pr: Option<u32>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        subject: Option<&'a str>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        author: Option<&'a str>,
+        /// Time spent compiling each crate, summed over all jobs
+        crate_totals: BTreeMap<&'a str, f64>,
+        /// The same as `crate_totals` for each cargo command, for the steps
+        /// where it's known
+        #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+        crate_totals_by_command: BTreeMap<&'a str, BTreeMap<&'a str, f64>>,
+        /// Descriptions of the `--incidents` this commit was built during
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        incidents: Vec<&'a str>,
     }
     #[derive(serde::Serialize)]
     struct Job<'a> {
         cpu_microarch: Option<&'a str>,
+        warnings: u32,
+        errors: u32,
     }
-    let mut data = Data::default();
-    for job in slowest_jobs {
-        let mut series = Series {
-            name: job,
-            data: Vec::new(),
-        };
-        for (_sha, commit) in commits.iter() {
-            match commit.jobs.get(job) {
-                Some(data) => {
-                    series.data.push(
-                        data.timings
-                            .iter()
-                            // Distcheck double-counts steps, so ignore it
-                            .filter(|(k, _)| *k != "Distcheck")
-                            .map(|(_, v)| v.dur)
-                            .sum(),
-                    );
+
+    fn validate(data: &Data<'_>, order: Order, order_by: OrderBy) -> Result<(), Error> {
+        let mut errors = Vec::new();
+        for series in data.series.iter() {
+            if series.data.len() != data.commits.len() {
+                errors.push(format!(
+                    "series `{}` has {} points but there are {} commits",
+                    series.name,
+                    series.data.len(),
+                    data.commits.len()
+                ));
+            }
+            for (i, point) in series.data.iter().enumerate() {
+                if !point.is_finite() || *point < 0.0 {
+                    errors.push(format!(
+                        "series `{}` has invalid duration {} at {}",
+                        series.name, point, i
+                    ));
+                }
+            }
+        }
+        let mut prev = None;
+        for commit in data.commits.iter() {
+            let date = match shared::parse_git_date(commit.date) {
+                Ok(date) => date,
+                Err(e) => {
+                    errors.push(format!("commit {} has invalid date: {}", commit.sha, e));
+                    continue;
+                }
+            };
+            // Topological order is allowed to disagree with the dates
+            if let (Some((prev_sha, prev)), OrderBy::Date) = (prev, order_by) {
+                let ordered = match order {
+                    Order::Asc => prev < date,
+                    Order::Desc => prev > date,
+                };
+                if !ordered {
+                    errors.push(format!(
+                        "commit {} is out of order with {}",
+                        commit.sha, prev_sha
+                    ));
                 }
-                None => series.data.push(0.0),
             }
+            prev = Some((commit.sha, date));
         }
-        data.series.push(series);
+
+        if errors.is_empty() {
+            return Ok(());
+        }
+        for error in errors.iter() {
+            eprintln!("invalid: {}", error);
+        }
+        bail!(
+            "generated data failed validation with {} errors",
+            errors.len()
+        )
+    }
+
+    let incidents = match &args.flag_incidents {
+        Some(path) => load_incidents(path)?,
+        None => Vec::new(),
+    };
+
+    let mut data = Data::default();
+    for job in slowest_jobs {
+        let mut points = commits
+            .iter()
+            .map(|(_sha, commit)| commit_job_total(commit, job, args.flag_aggregate_runs))
+            .collect::<Vec<Option<f64>>>();
+        let interpolated = if args.flag_interpolate_missing {
+            interpolate(&mut points)
+        } else {
+            Vec::new()
+        };
+        data.series.push(Series {
+            name: job,
+            data: points.into_iter().map(|p| p.unwrap_or(0.0)).collect(),
+            interpolated,
+            vs_baseline: None,
+        });
     }
     for (git, commit) in commits.iter() {
         data.commits.push(Commit {
             sha: &git.sha,
-            date: &git.date,
+            date: &git.date_raw,
             jobs: commit
                 .jobs
                 .iter()
@@ -130,72 +1045,1175 @@ fn write_overall(commits: &[(GitCommit, Commit)], out_dir: &Path) -> Result<(),
                     (
                         name.as_str(),
                         Job {
-                            cpu_microarch: job.cpu_microarch.as_ref().map(|cpu| cpu.as_str()),
+                            cpu_microarch: job.cpu_microarch.as_deref(),
+                            warnings: job.warnings,
+                            errors: job.errors,
                         },
                     )
                 })
                 .collect(),
+            approved_by: git.approved_by.as_deref(),
+            channel: git.channel.as_deref(),
+            pr: git.pr.filter(|_| args.flag_rich_metadata),
+            subject: Some(git.subject.as_str()).filter(|_| args.flag_rich_metadata),
+            author: git.author.as_deref().filter(|_| args.flag_rich_metadata),
+            crate_totals: crate_totals(args, commit),
+            crate_totals_by_command: crate_totals_by_command(args, commit),
+            incidents: incidents_during(&incidents, git.date),
         });
     }
-    data.commits.reverse();
-    for data in data.series.iter_mut() {
-        data.data.reverse();
+    /// An entry of `commits_index.json`, which lines up with the points of
+    /// every series so the frontend can look up what a point is.
+    #[derive(serde::Serialize)]
+    struct IndexEntry<'a> {
+        sha: &'a str,
+        date: &'a str,
+        pr: Option<u32>,
     }
-    let json = serde_json::to_string(&data)?;
-    fs::write(out_dir.join("overall.json"), json)?;
-    Ok(())
+    let mut index = commits
+        .iter()
+        .map(|(git, _)| IndexEntry {
+            sha: &git.sha,
+            date: &git.date_raw,
+            pr: git.pr,
+        })
+        .collect::<Vec<_>>();
+    // Commits come out of git newest-first
+    data.rolling_average = args
+        .flag_rolling_days
+        .map(|days| rolling_average(commits, days));
+    if args.flag_order == Order::Asc {
+        data.commits.reverse();
+        index.reverse();
+        if let Some(average) = &mut data.rolling_average {
+            average.reverse();
+        }
+        for data in data.series.iter_mut() {
+            data.data.reverse();
+            let len = data.data.len();
+            for i in data.interpolated.iter_mut() {
+                *i = len - 1 - *i;
+            }
+            data.interpolated.reverse();
+        }
+    }
+    if let Some(baseline) = baseline {
+        for series in data.series.iter_mut() {
+            let base = match baseline.jobs.get(series.name).map(job_total) {
+                Some(base) if base > 0.0 => base,
+                _ => continue,
+            };
+            series.vs_baseline = Some(
+                series
+                    .data
+                    .iter()
+                    .map(|point| (point - base) / base * 100.0)
+                    .collect(),
+            );
+        }
+    }
+    if args.flag_validate {
+        validate(&data, args.flag_order, args.flag_order_by)?;
+    }
+    output.write("commits_index.json", &index)?;
+    output.write("overall.json", &data)
+}
+
+/// The average total time of all jobs over the commits from `days` days
+/// before each of `commits` (newest first) up to it.
+///
+/// Commits without a valid date are averaged over just themselves.
+fn rolling_average(commits: &[(GitCommit, Commit)], days: u32) -> Vec<f64> {
+    let window = chrono::Duration::days(i64::from(days));
+    let points = commits
+        .iter()
+        .map(|(git, commit)| (git.date, commit_total(commit)))
+        .collect::<Vec<_>>();
+    points
+        .iter()
+        .map(|&(date, total)| {
+            let date = match date {
+                Some(date) => date,
+                None => return total,
+            };
+            let (sum, count) = points
+                .iter()
+                .filter_map(|&(other, total)| {
+                    let other = other?;
+                    Some(total).filter(|_| date - window < other && other <= date)
+                })
+                .fold((0.0, 0), |(sum, count), total| (sum + total, count + 1));
+            sum / f64::from(count)
+        })
+        .collect()
+}
+
+/// A known CI incident, whose description commits built during it are tagged
+/// with.
+#[derive(serde::Deserialize)]
+struct Incident {
+    start: String,
+    end: String,
+    description: String,
+}
+
+type IncidentRange = (
+    chrono::DateTime<chrono::FixedOffset>,
+    chrono::DateTime<chrono::FixedOffset>,
+    String,
+);
+
+/// Reads the `--incidents` file, with the same date formats as `export`.
+fn load_incidents(path: &Path) -> Result<Vec<IncidentRange>, Error> {
+    let incidents: Vec<Incident> = serde_json::from_slice(&fs::read(path)?)?;
+    incidents
+        .into_iter()
+        .map(|i| {
+            let start = parse_date(&i.start, false)?;
+            let end = parse_date(&i.end, true)?;
+            Ok((start, end, i.description))
+        })
+        .collect()
+}
+
+/// The descriptions of all of `incidents` covering `date`.
+fn incidents_during(
+    incidents: &[IncidentRange],
+    date: Option<chrono::DateTime<chrono::FixedOffset>>,
+) -> Vec<&str> {
+    let date = match date {
+        Some(date) => date,
+        None => return Vec::new(),
+    };
+    incidents
+        .iter()
+        .filter(|(start, end, _)| *start <= date && date <= *end)
+        .map(|(_, _, description)| description.as_str())
+        .collect()
+}
+
+/// Writes `config.json`, the analysis parameters the site was built with so
+/// the frontend can present the data the same way.
+fn write_config(
+    args: &Args,
+    baseline: Option<&str>,
+    output: &mut dyn OutputSink,
+) -> Result<(), Error> {
+    #[derive(serde::Serialize)]
+    struct Config<'a> {
+        order: Order,
+        order_by: OrderBy,
+        interpolate_missing: bool,
+        aggregate_runs: Option<Aggregate>,
+        decay_half_life: Option<f64>,
+        baseline: Option<&'a str>,
+        excluded_commits: &'a [String],
+        regression_threshold: f64,
+        rolling_days: Option<u32>,
+        excluded_crates: &'a [String],
+        min_commits: usize,
+    }
+    let config = Config {
+        order: args.flag_order,
+        order_by: args.flag_order_by,
+        interpolate_missing: args.flag_interpolate_missing,
+        aggregate_runs: args.flag_aggregate_runs,
+        decay_half_life: args.flag_decay_half_life,
+        baseline,
+        excluded_commits: &args.flag_exclude_commit,
+        regression_threshold: args.flag_regression_threshold,
+        rolling_days: args.flag_rolling_days,
+        excluded_crates: &args.flag_exclude_crate,
+        min_commits: args.flag_min_commits,
+    };
+    output.write("config.json", &config)
 }
 
-fn write_each_commit(commits: &[(GitCommit, Commit)], out_dir: &Path) -> Result<(), Error> {
+/// Writes `graph.json`, the hierarchy of CI groups and jobs of the most
+/// recent commit as edges from each group to what it contains.
+fn write_graph(commits: &[(GitCommit, Commit)], output: &mut dyn OutputSink) -> Result<(), Error> {
+    #[derive(serde::Serialize, Default)]
+    struct Graph<'a> {
+        jobs: Vec<GraphJob<'a>>,
+        /// `(parent, child)` pairs
+        edges: BTreeSet<(&'a str, &'a str)>,
+    }
+    #[derive(serde::Serialize)]
+    struct GraphJob<'a> {
+        name: &'a str,
+        order: Option<u32>,
+        total: f64,
+    }
+
+    let mut graph = Graph::default();
+    // Commits are newest-first
+    if let Some((_git, commit)) = commits.first() {
+        for (name, job) in commit.jobs.iter() {
+            graph.jobs.push(GraphJob {
+                name,
+                order: job.order,
+                total: job_total(job),
+            });
+            let mut child = name.as_str();
+            for parent in job.parents.iter() {
+                graph.edges.insert((parent, child));
+                child = parent;
+            }
+        }
+    }
+    output.write("graph.json", &graph)
+}
+
+/// Writes `reliability.json`, how often each job succeeded over all of the
+/// commits, to pick out the flaky ones.
+fn write_reliability(
+    commits: &[(GitCommit, Commit)],
+    output: &mut dyn OutputSink,
+) -> Result<(), Error> {
+    #[derive(serde::Serialize, Default)]
+    struct Reliability {
+        succeeded: u32,
+        failed: u32,
+        /// Canceled, skipped, or without a recorded result
+        other: u32,
+        /// Retries within a build, which hide failures that were retried away
+        retries: u32,
+        /// `succeeded` out of `succeeded` and `failed`, if there were either
+        pass_rate: Option<f64>,
+    }
+
+    let mut jobs = BTreeMap::new();
+    for (_git, commit) in commits {
+        for (name, job) in commit.jobs.iter() {
+            let entry = jobs
+                .entry(name.as_str())
+                .or_insert_with(Reliability::default);
+            match job.result.as_deref() {
+                Some("succeeded") | Some("succeededWithIssues") => entry.succeeded += 1,
+                Some("failed") => entry.failed += 1,
+                _ => entry.other += 1,
+            }
+            entry.retries += job.retries;
+        }
+    }
+    for job in jobs.values_mut() {
+        let runs = job.succeeded + job.failed;
+        if runs > 0 {
+            job.pass_rate = Some(f64::from(job.succeeded) / f64::from(runs));
+        }
+    }
+    output.write("reliability.json", &jobs)
+}
+
+/// How many of the crates which grew the most each regression lists.
+const TOP_CRATES: usize = 5;
+
+/// Writes `regressions.json`, every step of `commits` (newest first) which is
+/// more than `threshold` percent slower than in `baseline`, attributed to the
+/// crates in its `parts` which grew the most.
+fn write_regressions(
+    commits: &[(GitCommit, Commit)],
+    baseline: &Commit,
+    threshold: f64,
+    output: &mut dyn OutputSink,
+) -> Result<(), Error> {
+    #[derive(serde::Serialize)]
+    struct Regression<'a> {
+        sha: &'a str,
+        job: &'a str,
+        step: &'a str,
+        baseline: f64,
+        dur: f64,
+        pct: f64,
+        top_crates: Vec<CrateGrowth<'a>>,
+    }
+
+    let mut regressions = Vec::new();
     for (git, commit) in commits {
-        let dst = out_dir.join(&git.sha).with_extension("json");
-        let json = serde_json::to_string(&commit)?;
-        fs::write(&dst, json)?;
+        for (job, data) in commit.jobs.iter() {
+            let base = match baseline.jobs.get(job) {
+                Some(base) => base,
+                None => continue,
+            };
+            for (step, timing) in data.timings.iter() {
+                let base = match base.timings.get(step) {
+                    Some(base) if base.dur > 0.0 => base,
+                    _ => continue,
+                };
+                let pct = (timing.dur - base.dur) / base.dur * 100.0;
+                if pct <= threshold {
+                    continue;
+                }
+                regressions.push(Regression {
+                    sha: &git.sha,
+                    job,
+                    step,
+                    baseline: base.dur,
+                    dur: timing.dur,
+                    pct,
+                    top_crates: top_crates(&base.parts, &timing.parts),
+                });
+            }
+        }
     }
-    Ok(())
+    output.write("regressions.json", &regressions)
+}
+
+/// How a crate's time in a step changed from the baseline.
+#[derive(serde::Serialize, Debug, PartialEq)]
+struct CrateGrowth<'a> {
+    name: &'a str,
+    baseline: f64,
+    dur: f64,
+    delta: f64,
 }
 
-fn get_commits(rust: &Path, cache: &Path) -> Result<Vec<(GitCommit, Commit)>, Error> {
-    let commits = shared::get_git_commits(rust)?
-        .take(100)
-        .collect::<Result<Vec<_>, Error>>()?;
+/// The crates of `parts` which took longer than in `base`, most grown first,
+/// with crates new since the baseline having grown from nothing.
+fn top_crates<'a>(
+    base: &BTreeMap<String, f64>,
+    parts: &'a BTreeMap<String, f64>,
+) -> Vec<CrateGrowth<'a>> {
+    let mut ret = parts
+        .iter()
+        .map(|(name, dur)| {
+            let baseline = base.get(name).cloned().unwrap_or(0.0);
+            CrateGrowth {
+                name,
+                baseline,
+                dur: *dur,
+                delta: dur - baseline,
+            }
+        })
+        .filter(|growth| growth.delta > 0.0)
+        .collect::<Vec<_>>();
+    ret.sort_by(|a, b| b.delta.total_cmp(&a.delta));
+    ret.truncate(TOP_CRATES);
+    ret
+}
+
+/// The hourly cost of the jobs whose names match the `jobs` glob.
+#[derive(serde::Deserialize)]
+struct CostRate {
+    jobs: String,
+    per_hour: f64,
+}
 
-    let mut urls = Vec::new();
+/// Writes `cost.json`, what each job of every commit (newest first) cost to
+/// run at the first of `rates` matching it.
+fn write_cost(
+    commits: &[(GitCommit, Commit)],
+    rates: &[CostRate],
+    output: &mut dyn OutputSink,
+) -> Result<(), Error> {
+    #[derive(serde::Serialize)]
+    struct Cost<'a> {
+        sha: &'a str,
+        total: f64,
+        jobs: BTreeMap<&'a str, f64>,
+    }
+
+    let mut costs = Vec::new();
+    for (git, commit) in commits {
+        let mut jobs = BTreeMap::new();
+        let mut total = 0.0;
+        for (name, job) in counted_jobs(commit) {
+            let rate = rates
+                .iter()
+                .find(|rate| glob_match(&rate.jobs, name))
+                .map(|rate| rate.per_hour)
+                .unwrap_or(0.0);
+            let cost = job_total(job) / 3600.0 * rate;
+            jobs.insert(name.as_str(), cost);
+            total += cost;
+        }
+        costs.push(Cost {
+            sha: &git.sha,
+            total,
+            jobs,
+        });
+    }
+    output.write("cost.json", &costs)
+}
+
+/// Whether `name` matches `pattern`, where `*` matches any run of characters
+/// and `?` any single one.
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+    // The position after the last `*` seen and how much of `name` it's taken
+    let mut star = None;
+    let (mut p, mut n) = (0, 0);
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p + 1, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((after, taken)) => {
+                    p = after;
+                    n = taken + 1;
+                    star = Some((after, taken + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// The time spent compiling each crate in `commit`, summing the parts of
+/// every step across all of its jobs.
+fn crate_totals<'a>(args: &Args, commit: &'a Commit) -> BTreeMap<&'a str, f64> {
+    let mut totals = BTreeMap::new();
+    for (name, job) in commit.jobs.iter() {
+        // Distcheck double-counts steps, so ignore it
+        if name == "Distcheck" {
+            continue;
+        }
+        for timing in job.timings.values() {
+            for (krate, dur) in timing.parts.iter() {
+                if let Some(krate) = crate_key(args, krate) {
+                    *totals.entry(krate).or_insert(0.0) += dur;
+                }
+            }
+        }
+    }
+    totals
+}
+
+/// Like `crate_totals`, but split up by the cargo command of each step.
+fn crate_totals_by_command<'a>(
+    args: &Args,
+    commit: &'a Commit,
+) -> BTreeMap<&'a str, BTreeMap<&'a str, f64>> {
+    let mut totals = BTreeMap::new();
+    for (name, job) in commit.jobs.iter() {
+        if name == "Distcheck" {
+            continue;
+        }
+        for timing in job.timings.values() {
+            let command = match &timing.cargo_command {
+                Some(command) => command.as_str(),
+                None => continue,
+            };
+            let totals = totals.entry(command).or_insert_with(BTreeMap::new);
+            for (krate, dur) in timing.parts.iter() {
+                if let Some(krate) = crate_key(args, krate) {
+                    *totals.entry(krate).or_insert(0.0) += dur;
+                }
+            }
+        }
+    }
+    totals
+}
+
+/// What `--exclude-crate` crates are totaled as with `--keep-excluded-crates`.
+const EXCLUDED_CRATES: &str = "excluded";
+
+/// The name `krate`'s time is totaled under, if it's totaled at all.
+///
+/// Globs only match the crate's name, not the ` test:<bool>` which follows
+/// it in the parts of a step.
+fn crate_key<'a>(args: &Args, krate: &'a str) -> Option<&'a str> {
+    let name = krate.split(" test:").next().unwrap_or(krate);
+    if !args
+        .flag_exclude_crate
+        .iter()
+        .any(|glob| glob_match(glob, name))
+    {
+        Some(krate)
+    } else if args.flag_keep_excluded_crates {
+        Some(EXCLUDED_CRATES)
+    } else {
+        None
+    }
+}
+
+/// The jobs of `commit` which count towards its total time, which is all but
+/// Distcheck since it double-counts steps of the others.
+fn counted_jobs(commit: &Commit) -> impl Iterator<Item = (&String, &shared::Job)> {
+    commit.jobs.iter().filter(|(name, _)| *name != "Distcheck")
+}
+
+/// The total time taken by all of the `counted_jobs` of `commit`.
+fn commit_total(commit: &Commit) -> f64 {
+    let mut total = 0.0;
+    for (_name, job) in counted_jobs(commit) {
+        total += job_total(job);
+    }
+    total
+}
+
+/// The total time taken by `job`, summed over all of its steps.
+fn job_total(job: &shared::Job) -> f64 {
+    job.timings
+        .iter()
+        // Distcheck double-counts steps, so ignore it
+        .filter(|(k, _)| *k != "Distcheck")
+        .map(|(_, v)| v.dur)
+        .sum()
+}
+
+/// The total time of the job `name` in `commit`, optionally aggregated over all
+/// of the commit's runs.
+fn commit_job_total(commit: &Commit, name: &str, aggregate: Option<Aggregate>) -> Option<f64> {
+    let primary = commit.jobs.get(name).map(job_total);
+    let aggregate = match aggregate {
+        Some(aggregate) => aggregate,
+        None => return primary,
+    };
+    let mut totals = primary
+        .into_iter()
+        .chain(
+            commit
+                .reruns
+                .iter()
+                .filter_map(|jobs| jobs.get(name).map(job_total)),
+        )
+        .collect::<Vec<_>>();
+    if totals.is_empty() {
+        return None;
+    }
+    totals.sort_by(f64::total_cmp);
+    Some(match aggregate {
+        Aggregate::Min => totals[0],
+        Aggregate::Median if totals.len() % 2 == 0 => {
+            (totals[totals.len() / 2 - 1] + totals[totals.len() / 2]) / 2.0
+        }
+        Aggregate::Median => totals[totals.len() / 2],
+    })
+}
+
+/// Fills in each missing point linearly from the nearest present points on
+/// either side, returning the indices which were filled in.
+///
+/// Points at either end with only one neighbor just copy it, and if there are
+/// no present points at all nothing is filled in.
+fn interpolate(points: &mut [Option<f64>]) -> Vec<usize> {
+    let mut filled = Vec::new();
+    for i in 0..points.len() {
+        if points[i].is_some() {
+            continue;
+        }
+        let before = (0..i).rev().find_map(|j| points[j].map(|p| (j, p)));
+        let after = (i + 1..points.len()).find_map(|j| points[j].map(|p| (j, p)));
+        let value = match (before, after) {
+            (Some((a, pa)), Some((b, pb))) => pa + (pb - pa) * (i - a) as f64 / (b - a) as f64,
+            (Some((_, p)), None) | (None, Some((_, p))) => p,
+            (None, None) => continue,
+        };
+        points[i] = Some(value);
+        filled.push(i);
+    }
+    filled
+}
+
+/// Writes out the data of each commit as `<sha>.json`.
+///
+/// When `content_addressed` is set the files are instead named after a hash
+/// of their contents, so they never change once written and can be cached
+/// indefinitely. The mutable `index.json` then maps each sha to its file.
+fn write_each_commit(
+    commits: &[(GitCommit, Commit)],
+    content_addressed: bool,
+    output: &mut dyn OutputSink,
+) -> Result<(), Error> {
+    if !content_addressed {
+        for (git, commit) in commits {
+            output.write(&format!("{}.json", git.sha), commit)?;
+        }
+        return Ok(());
+    }
+    let mut index = BTreeMap::new();
+    for (git, commit) in commits {
+        let json = output.to_json(commit)?;
+        let name = format!("commits/{:016x}.json", shared::stable_hash(&json));
+        output.write_file(&name, json)?;
+        index.insert(git.sha.as_str(), name);
+    }
+    output.write("index.json", &index)
+}
+
+fn get_commits(args: &Args) -> Result<Vec<(GitCommit, Commit)>, Error> {
+    let rust = &args.arg_rust_repo;
+    let cache = &args.arg_cache_dir;
+    let s3_endpoint = args.flag_s3_endpoint.as_deref();
+    let refresh = args.flag_force_refresh.as_deref();
+    let mut commits = if args.flag_from_manifest {
+        if args.flag_order_by == OrderBy::Topo {
+            bail!("`--order-by topo` needs the git history, not `--from-manifest`");
+        }
+        // The manifest changes with every publish, so it's always downloaded
+        let manifest = shared::download_manifest(&shared::s3_url(s3_endpoint))?;
+        let manifest = match manifest {
+            Some(manifest) => manifest,
+            None => bail!("no manifest has been published"),
+        };
+        select_commits(args, manifest.iter().map(|entry| Ok(entry.git_commit())))?
+    } else {
+        select_commits(args, shared::get_git_commits(rust, Some("bors"))?)?
+    };
+    match &args.flag_branch_from_subject {
+        Some(re) => {
+            let re = regex::Regex::new(re)?;
+            for commit in commits.iter_mut() {
+                commit.channel = branch_from(&re, &commit.subject);
+            }
+        }
+        None if !args.flag_from_manifest => shared::detect_channels(rust, &mut commits)?,
+        None => {}
+    }
+    if args.flag_order_by == OrderBy::Topo {
+        // Anything not on the first-parent history sorts as the oldest
+        let positions = shared::first_parent_positions(rust)?;
+        commits.sort_by_key(|c| positions.get(&c.sha).cloned().unwrap_or(usize::MAX));
+    }
+    let shas = commits.iter().map(|c| c.sha.as_str()).collect::<Vec<_>>();
+    let data = load_commits(cache, &shas, s3_endpoint, refresh)?;
+    Ok(commits.into_iter().zip(data).collect())
+}
+
+/// How many of the most recent commits are charted without `--since`.
+const DEFAULT_COMMITS: usize = 100;
+
+/// Picks the commits within `--since` and `--until` out of `commits`
+/// (newest first), or only the most recent ones without `--since`.
+fn select_commits(
+    args: &Args,
+    commits: impl Iterator<Item = Result<GitCommit, Error>>,
+) -> Result<Vec<GitCommit>, Error> {
+    let since = match &args.flag_since {
+        Some(since) => Some(parse_date(since, false)?),
+        None => None,
+    };
+    let until = match &args.flag_until {
+        Some(until) => Some(parse_date(until, true)?),
+        None => None,
+    };
+    if since.is_none() && until.is_none() {
+        return commits.take(DEFAULT_COMMITS).collect();
+    }
+    let mut ret = Vec::new();
+    let mut undated = 0;
+    for commit in commits {
+        let commit = commit?;
+        let date = match commit.date {
+            Some(date) => date,
+            None => {
+                undated += 1;
+                continue;
+            }
+        };
+        if until.is_some_and(|until| date > until) {
+            continue;
+        }
+        if since.is_some_and(|since| date < since) {
+            break;
+        }
+        ret.push(commit);
+        if since.is_none() && ret.len() == DEFAULT_COMMITS {
+            break;
+        }
+    }
+    if undated > 0 {
+        log::warn!("left out {} commits whose date couldn't be parsed", undated);
+    }
+    Ok(ret)
+}
+
+/// The label `re` picks out of `subject`, its first capture group if it has
+/// one or otherwise the whole match.
+fn branch_from(re: &regex::Regex, subject: &str) -> Option<String> {
+    let caps = re.captures(subject)?;
+    let label = caps.get(1).or_else(|| caps.get(0))?;
+    Some(label.as_str().to_string())
+}
+
+/// Loads the published data for each of `shas` from the cache, downloading
+/// any which aren't cached yet or which `--force-refresh` selects.
+fn load_commits(
+    cache: &Path,
+    shas: &[&str],
+    s3_endpoint: Option<&str>,
+    refresh: Option<&str>,
+) -> Result<Vec<Commit>, Error> {
+    let mut missing = Vec::new();
     let commits_dir = cache.join("commits");
     let mut paths = Vec::new();
-    for commit in commits.iter() {
-        let path = commits_dir.join(&commit.sha).with_extension("json.gz");
+    for sha in shas {
+        let path = commits_dir.join(sha).with_extension("json.gz");
+        if path.exists() && should_refresh(refresh, sha) {
+            log::debug!("removing {:?} to refresh it", path);
+            fs::remove_file(&path)?;
+        }
         if !path.exists() {
-            let url = format!(
-                "https://{}.s3.amazonaws.com/commits/{}.json.gz",
-                env::var("S3_BUCKET").expect("missing environment variable S3_BUCKET"),
-                commit.sha
-            );
-            urls.push(url);
+            missing.push(*sha);
         }
         paths.push(path);
     }
 
-    if urls.len() > 0 {
-        println!("downloading {:#?}", urls);
-        fs::create_dir_all(&commits_dir)?;
-        let status = Command::new("curl")
-            .arg("--remote-name-all")
-            .arg("-f")
-            .args(&urls)
-            .current_dir(commits_dir)
-            .status()?;
-        assert!(status.success());
+    if !missing.is_empty() {
+        shared::download_commits(&shared::s3_url(s3_endpoint), &missing, &commits_dir)?;
     }
 
     let mut ret = Vec::new();
-    for (commit, path) in commits.into_iter().zip(&paths) {
+    for path in paths.iter() {
         log::debug!("reading {:?}", path);
-        let raw = fs::read(path)?;
-        let mut json = String::new();
-        flate2::read::GzDecoder::new(&raw[..]).read_to_string(&mut json)?;
-        let json: shared::Commit = serde_json::from_str(&json)?;
-        ret.push((commit, json));
+        ret.push(shared::decode_commit(fs::File::open(path)?)?);
+    }
+
+    // Delta-encoded commits need their base, which is usually amongst the
+    // commits just loaded but otherwise is loaded on its own.
+    let mut bases = BTreeMap::new();
+    for commit in ret.iter() {
+        let base = match &commit.delta_base {
+            Some(base) if !bases.contains_key(base) => base,
+            _ => continue,
+        };
+        let loaded = match shas.iter().position(|sha| sha == base) {
+            Some(i) => ret[i].clone(),
+            None => load_commits(cache, &[base.as_str()], s3_endpoint, refresh)?
+                .pop()
+                .unwrap(),
+        };
+        bases.insert(base.clone(), loaded);
+    }
+    for commit in ret.iter_mut() {
+        if let Some(base) = commit.delta_base.clone() {
+            shared::delta_decode(commit, &bases[&base])?;
+        }
     }
     Ok(ret)
 }
+
+/// Whether `sha` is selected by `--force-refresh`, which is either `all` or a
+/// comma-separated list of shas as expanded by `resolve_args`.
+fn should_refresh(refresh: Option<&str>, sha: &str) -> bool {
+    match refresh {
+        Some("all") => true,
+        Some(list) => list.split(',').any(|full| full == sha),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    /// Keeps everything written in memory.
+    struct MemorySink {
+        files: BTreeMap<String, Vec<u8>>,
+        pretty: bool,
+    }
+
+    impl OutputSink for MemorySink {
+        fn write_file(&mut self, name: &str, contents: Vec<u8>) -> Result<(), Error> {
+            self.files.insert(name.to_string(), contents);
+            Ok(())
+        }
+
+        fn pretty(&self) -> bool {
+            self.pretty
+        }
+    }
+
+    /// A commit with nothing but a SHA and a date, which may be unparseable.
+    fn git(sha: &str, date: &str) -> GitCommit {
+        GitCommit {
+            sha: sha.to_string(),
+            date: chrono::DateTime::parse_from_rfc3339(date).ok(),
+            date_raw: date.to_string(),
+            subject: String::new(),
+            pr: None,
+            author: None,
+            approved_by: None,
+            channel: None,
+        }
+    }
+
+    /// Parses `flags` as given on the command line, before the positionals.
+    fn args(flags: &[&str]) -> Args {
+        let mut argv = vec!["build-site"];
+        argv.extend(flags);
+        argv.extend(["rust", "cache", "out"]);
+        docopt::Docopt::new(USAGE)
+            .and_then(|d| d.argv(argv).deserialize())
+            .unwrap()
+    }
+
+    fn commit(build_dur: f64, syn: f64, serde: f64) -> Commit {
+        serde_json::from_value(serde_json::json!({
+            "build_dur": build_dur,
+            "jobs": {
+                "x86_64-gnu": {
+                    "url": "https://example.com/1",
+                    "path": "",
+                    "cpu_microarch": "Skylake",
+                    "warnings": 2,
+                    "timings": {
+                        "build std": {
+                            "dur": 100.0 + syn,
+                            "parts": { "syn": syn, "serde": serde },
+                            "cargo_command": "build",
+                        },
+                        "check std": { "dur": 20.0, "parts": { "syn": syn / 4.0 } },
+                    },
+                },
+                "dist-x86_64-linux": {
+                    "url": "https://example.com/2",
+                    "path": "",
+                    "cpu_microarch": null,
+                    "timings": { "dist": { "dur": 300.0, "parts": {} } },
+                },
+            },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn branch_from_subject() {
+        let re = regex::Regex::new(r"^\[(\w+)\]").unwrap();
+        let branch = |s| branch_from(&re, s);
+        assert_eq!(branch("[beta] Backport #123").as_deref(), Some("beta"));
+        assert_eq!(branch("Merge #123"), None);
+        let re = regex::Regex::new(r"release-\d+").unwrap();
+        let branch = branch_from(&re, "Merge into release-42 (#1)");
+        assert_eq!(branch.as_deref(), Some("release-42"));
+    }
+
+    #[test]
+    fn movers_in_both_directions() {
+        let prev = commit(390.0, 1.25, 2.0);
+        let mut latest = commit(400.0, 11.25, 2.0);
+        let dist = latest.jobs.get_mut("dist-x86_64-linux").unwrap();
+        dist.timings.get_mut("dist").unwrap().dur = 270.0;
+        let movers = movers(&prev, &latest);
+        let found = movers
+            .iter()
+            .map(|m| (m.job, m.step, m.delta, m.pct))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            [
+                ("dist-x86_64-linux", "dist", -30.0, -10.0),
+                ("x86_64-gnu", "build std", 10.0, 10.0 / 101.25 * 100.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn glob_matching() {
+        assert!(glob_match("*", "x86_64-gnu"));
+        assert!(glob_match("x86_64-*", "x86_64-gnu"));
+        assert!(glob_match("*-msvc-?", "x86_64-msvc-1"));
+        assert!(glob_match("dist-*-linux", "dist-x86_64-linux"));
+        assert!(!glob_match("dist-*-linux", "dist-x86_64-linux-alt"));
+        assert!(!glob_match("x86_64-gnu", "x86_64-gnu-llvm"));
+    }
+
+    #[test]
+    fn cost_from_first_matching_rate() {
+        let rates: Vec<CostRate> = serde_json::from_value(serde_json::json!([
+            { "jobs": "dist-*", "per_hour": 2.0 },
+            { "jobs": "*", "per_hour": 1.0 },
+        ]))
+        .unwrap();
+        let git = git("c1", "");
+        let commits = [(git, commit(0.0, 0.0, 0.0))];
+        let mut output = MemorySink {
+            files: BTreeMap::new(),
+            pretty: false,
+        };
+        write_cost(&commits, &rates, &mut output).unwrap();
+        let cost: serde_json::Value = serde_json::from_slice(&output.files["cost.json"]).unwrap();
+        // 300s of dist at $2/h, 120s of the rest at $1/h
+        assert_eq!(cost[0]["jobs"]["dist-x86_64-linux"], 300.0 / 3600.0 * 2.0);
+        assert_eq!(cost[0]["jobs"]["x86_64-gnu"], 120.0 / 3600.0);
+    }
+
+    #[test]
+    fn totals_leave_out_distcheck() {
+        let mut commit = commit(0.0, 20.0, 1.0);
+        let dist = commit.jobs["dist-x86_64-linux"].clone();
+        commit.jobs.insert("Distcheck".to_string(), dist);
+        // 120s of `build std`, 20s of `check std` and 300s of `dist`
+        assert_eq!(commit_total(&commit), 440.0);
+
+        let rates: Vec<CostRate> =
+            serde_json::from_value(serde_json::json!([{ "jobs": "*", "per_hour": 1.0 }])).unwrap();
+        let git = git("c1", "");
+        let mut output = MemorySink {
+            files: BTreeMap::new(),
+            pretty: false,
+        };
+        write_cost(&[(git, commit)], &rates, &mut output).unwrap();
+        let cost: serde_json::Value = serde_json::from_slice(&output.files["cost.json"]).unwrap();
+        assert_eq!(cost[0]["total"], 440.0 / 3600.0);
+        assert!(cost[0]["jobs"].get("Distcheck").is_none());
+    }
+
+    #[test]
+    fn commits_during_incidents() {
+        let path = std::env::temp_dir().join(format!("incidents-{}.json", process::id()));
+        let json = serde_json::json!([
+            { "start": "2020-01-02", "end": "2020-01-03", "description": "slow runners" },
+            { "start": "2020-01-03T12:00:00Z", "end": "2020-01-04", "description": "outage" },
+        ]);
+        fs::write(&path, json.to_string()).unwrap();
+        let incidents = load_incidents(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let during = |date| {
+            let date = chrono::DateTime::parse_from_rfc3339(date).ok();
+            incidents_during(&incidents, date)
+        };
+        assert!(during("2020-01-01T23:59:59+00:00").is_empty());
+        assert_eq!(during("2020-01-03T06:00:00+00:00"), ["slow runners"]);
+        assert_eq!(
+            during("2020-01-03T18:00:00+00:00"),
+            ["slow runners", "outage"]
+        );
+        assert!(during("not a date").is_empty());
+    }
+
+    #[test]
+    fn regressions_list_the_crates_which_grew() {
+        let baseline = commit(390.0, 1.0, 2.0);
+        let git = git("c1", "");
+        let commits = [(git, commit(400.0, 20.0, 1.0))];
+        let mut output = MemorySink {
+            files: BTreeMap::new(),
+            pretty: false,
+        };
+        write_regressions(&commits, &baseline, 10.0, &mut output).unwrap();
+        let json: serde_json::Value =
+            serde_json::from_slice(&output.files["regressions.json"]).unwrap();
+        // `build std` grew by 19s out of 101s while `check std` stayed put
+        let regressions = json.as_array().unwrap();
+        assert_eq!(regressions.len(), 1);
+        assert_eq!(regressions[0]["step"], "build std");
+        assert_eq!(
+            regressions[0]["top_crates"],
+            serde_json::json!([{ "name": "syn", "baseline": 1.0, "dur": 20.0, "delta": 19.0 }])
+        );
+    }
+
+    #[test]
+    fn regressions_need_min_commits() {
+        let baseline = commit(390.0, 1.0, 2.0);
+        let git = git("c1", "");
+        let commits = [(git, commit(400.0, 20.0, 1.0))];
+        let build = |min_commits: &str| {
+            let args = args(&["--min-commits", min_commits]);
+            let mut output = MemorySink {
+                files: BTreeMap::new(),
+                pretty: false,
+            };
+            build(&args, &commits, Some(("c0", &baseline)), &mut output).unwrap();
+            output.files
+        };
+        assert!(build("1").contains_key("regressions.json"));
+        assert!(!build("2").contains_key("regressions.json"));
+    }
+
+    #[test]
+    fn rolling_average_over_days() {
+        let commits = [
+            ("2020-01-09T00:00:00+00:00", 600.0),
+            ("2020-01-03T00:00:00+00:00", 500.0),
+            ("2020-01-02T12:00:00+00:00", 400.0),
+            ("2020-01-01T00:00:00+00:00", 390.0),
+            ("unknown", 100.0),
+        ]
+        .iter()
+        .map(|(date, total)| {
+            // `commit` adds 120s of other steps and 300s of `dist` to `syn`
+            (git("", date), commit(0.0, total - 420.0, 0.0))
+        })
+        .collect::<Vec<_>>();
+        assert_eq!(
+            rolling_average(&commits, 7),
+            [(600.0 + 500.0 + 400.0) / 3.0, 430.0, 395.0, 390.0, 100.0]
+        );
+    }
+
+    #[test]
+    fn excluded_crates() {
+        let commit: Commit = serde_json::from_value(serde_json::json!({
+            "jobs": {
+                "x86_64-gnu": {
+                    "url": "",
+                    "path": "",
+                    "cpu_microarch": null,
+                    "timings": {
+                        "build std": {
+                            "dur": 10.0,
+                            "parts": {
+                                "syn test:false": 1.5,
+                                "serde test:false": 2.0,
+                                "serde_json test:false": 0.5,
+                            },
+                            "cargo_command": "build",
+                        },
+                        "test std": {
+                            "dur": 1.0,
+                            "parts": { "serde test:true": 0.25 },
+                            "cargo_command": "test",
+                        },
+                    },
+                },
+            },
+        }))
+        .unwrap();
+        let totals = crate_totals(&args(&["--exclude-crate", "serde"]), &commit);
+        assert_eq!(
+            totals.into_iter().collect::<Vec<_>>(),
+            [("serde_json test:false", 0.5), ("syn test:false", 1.5)]
+        );
+        let args = args(&["--exclude-crate", "s*", "--keep-excluded-crates"]);
+        let totals = crate_totals(&args, &commit);
+        assert_eq!(totals.into_iter().collect::<Vec<_>>(), [("excluded", 4.25)]);
+        let totals = crate_totals_by_command(&args, &commit);
+        assert_eq!(totals["build"]["excluded"], 4.0);
+        assert_eq!(totals["test"]["excluded"], 0.25);
+    }
+
+    #[test]
+    fn commits_since_and_until() {
+        let commits = (1..=9).rev().map(|day| {
+            let entry = shared::ManifestEntry {
+                sha: day.to_string(),
+                date: format!("2020-01-0{}T12:00:00+00:00", day),
+                subject: String::new(),
+            };
+            Ok(entry.git_commit())
+        });
+        let select = |flags: &[&str]| {
+            select_commits(&args(flags), commits.clone())
+                .unwrap()
+                .into_iter()
+                .map(|c| c.sha)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(select(&[]).len(), 9);
+        assert_eq!(
+            select(&["--since", "2020-01-03", "--until", "2020-01-05"]),
+            ["5", "4", "3"]
+        );
+        assert_eq!(select(&["--since", "2020-01-08"]), ["9", "8"]);
+        assert_eq!(select(&["--until", "2020-01-02T00:00:00Z"]), ["1"]);
+    }
+
+    /// Runs the whole transformation from cached commits to `overall.json`,
+    /// which is compared against `tests/golden/overall.json`.
+    ///
+    /// Run with `BLESS=1` to update the golden file after an intended change.
+    #[test]
+    fn overall_json_matches_golden() {
+        let dir = std::env::temp_dir().join(format!("build-site-golden-{}", process::id()));
+        let cache = dir.join("cache");
+        fs::create_dir_all(cache.join("commits")).unwrap();
+
+        let cached = [
+            ("c3", "2020-01-03T00:00:00+00:00", commit(400.0, 1.5, 2.5)),
+            ("c2", "2020-01-02T00:00:00+00:00", commit(380.0, 1.0, 2.5)),
+            ("c1", "2020-01-01T00:00:00+00:00", commit(390.0, 1.25, 2.0)),
+        ];
+        for (sha, _, commit) in cached.iter() {
+            let path = cache.join("commits").join(sha).with_extension("json.gz");
+            let file = fs::File::create(path).unwrap();
+            let mut gz = flate2::write::GzEncoder::new(file, flate2::Compression::best());
+            serde_json::to_writer(&mut gz, commit).unwrap();
+            gz.finish().unwrap().flush().unwrap();
+        }
+        let shas = cached.iter().map(|(sha, ..)| *sha).collect::<Vec<_>>();
+        let loaded = load_commits(&cache, &shas, None, None).unwrap();
+        let commits = cached
+            .iter()
+            .zip(loaded)
+            .map(|((sha, date, _), commit)| {
+                let git = GitCommit {
+                    subject: format!("Auto merge of #1 - bob:{}, r=alice", sha),
+                    pr: Some(1),
+                    author: Some("bob".to_string()),
+                    approved_by: Some("alice".to_string()),
+                    channel: Some("nightly".to_string()),
+                    ..git(sha, date)
+                };
+                (git, commit)
+            })
+            .collect::<Vec<_>>();
+
+        let args = args(&["--pretty"]);
+        let mut output = MemorySink {
+            files: BTreeMap::new(),
+            pretty: args.flag_pretty,
+        };
+        build(&args, &commits, None, &mut output).unwrap();
+
+        let actual = String::from_utf8(output.files.remove("overall.json").unwrap()).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let golden = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/overall.json");
+        if std::env::var_os("BLESS").is_some() {
+            fs::write(&golden, &actual).unwrap();
+        }
+        assert_eq!(actual, fs::read_to_string(&golden).unwrap());
+    }
+
+    #[test]
+    fn ranking_with_decay() {
+        let commits = [40.0, 0.0]
+            .iter()
+            .map(|&syn| (git("", ""), commit(0.0, syn, 1.0)))
+            .collect::<Vec<_>>();
+        let ranking = |flags: &[&str]| {
+            let mut flags = flags.to_vec();
+            flags.extend(["--min-commits", "1"]);
+            let args = args(&flags);
+            let mut output = MemorySink {
+                files: BTreeMap::new(),
+                pretty: false,
+            };
+            write_overall(&args, &commits, None, &mut output).unwrap();
+            let ranking: serde_json::Value =
+                serde_json::from_slice(&output.files["ranking.json"]).unwrap();
+            ranking
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|rank| rank["name"] == "x86_64-gnu")
+                .unwrap()
+                .clone()
+        };
+        // `commit` has 120s of steps besides `syn`
+        let rank = ranking(&[]);
+        assert_eq!(rank["average"], 140.0);
+        assert!(rank.get("weighted_average").is_none());
+        let rank = ranking(&["--decay-half-life", "1"]);
+        assert_eq!(rank["average"], 140.0);
+        assert_eq!(rank["weighted_average"], (160.0 + 120.0 * 0.5) / 1.5);
+    }
+
+    #[test]
+    fn content_addressed_names_are_stable() {
+        let git = git("c1", "");
+        let commits = [(git, commit(400.0, 20.0, 1.0))];
+        let mut output = MemorySink {
+            files: BTreeMap::new(),
+            pretty: false,
+        };
+        write_each_commit(&commits, true, &mut output).unwrap();
+        let index: BTreeMap<String, String> =
+            serde_json::from_slice(&output.files["index.json"]).unwrap();
+        // Changing how names are hashed needlessly busts every cached file,
+        // so this is pinned rather than recomputed
+        assert_eq!(index["c1"], "commits/2a0e07d24c168e98.json");
+        assert!(output.files.contains_key(&index["c1"]));
+    }
+}
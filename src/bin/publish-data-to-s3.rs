@@ -1,76 +1,674 @@
 use failure::{bail, format_err, Error};
 use rayon::prelude::*;
-use shared::{Commit, Job, Timing};
-use std::collections::{BTreeMap, HashMap};
-use std::env;
+use regex::Regex;
+use shared::{CargoBuild, Commit, GitCommit, Job, LogFormat, ManifestEntry, Parallelism, Timing};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
-use std::io::{Read, Write};
+use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
 use std::process::{self, Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 
 struct Context {
-    azure: HashMap<String, azure::Build>,
+    /// All finished builds of each commit, oldest first.
+    azure: HashMap<String, Vec<azure::Build>>,
+    /// Commits whose builds are still running (or were canceled), these are
+    /// skipped rather than publishing partial data.
+    unfinished: HashSet<String>,
+    /// Where the next page of Azure's list of builds starts, if it's been
+    /// listed at all
+    azure_continuation: Option<String>,
+    /// Set once every page of Azure's builds has been listed
+    azure_exhausted: bool,
+    /// Finished GitLab pipelines of each commit, oldest first, fetched lazily
+    /// as commits are processed.
+    gitlab: HashMap<String, Vec<gitlab::Pipeline>>,
+    /// Completed GitHub Actions runs of each commit, oldest first, fetched
+    /// lazily as commits are processed.
+    github: HashMap<String, Vec<github::Run>>,
+    ci: Ci,
+    github_repo: String,
+    gitlab_url: String,
+    gitlab_project: Option<String>,
+    /// Marker preceding the job's name in its log
+    job_name_needle: String,
+    /// Fetched logs shorter than this are assumed to be truncated
+    min_log_bytes: usize,
+    /// Percentage of a commit's logs which may fail to parse before it's
+    /// considered broken
+    max_parse_failures: f64,
+    /// With `--delta-encode`, how many commits share each full commit as
+    /// their base
+    delta_every: Option<usize>,
+    /// The last commit cached in full, and how many commits have been cached
+    /// since including it.
+    delta_base: Option<(String, Commit, usize)>,
     cache: PathBuf,
+    /// Whether failing to write to `cache` is only a warning
+    cache_readonly: bool,
+    parser_version: u32,
+    s3_endpoint: Option<String>,
+    /// Timeouts of every request, in seconds, so a hung connection can't
+    /// wedge the whole run
+    connect_timeout: u64,
+    max_time: u64,
+    pretty: bool,
+    /// Additional markers for steps, each with `name` and `dur` groups
+    timing_regexes: Vec<Regex>,
+    /// Steps which crates compiled are attributed to, or any step if `None`
+    flush_target: Option<Regex>,
+    /// Crates taking less time than this in a step are lumped together
+    min_part_secs: f64,
+    /// Optional parsers, out of `OPTIONAL_PARSERS`, which are skipped
+    skip_parsers: HashSet<String>,
+    /// Whether to trim and collapse whitespace in step names
+    normalize_steps: bool,
+    /// Whether to also lowercase step names
+    lowercase_steps: bool,
+    /// Whether to print the timing lines of each log as it's parsed
+    dump_raw_timings: bool,
+    /// Commits being compressed on the thread pool, see `finish_writes`
+    pending_writes: Mutex<Vec<mpsc::Receiver<Result<Compressed, Error>>>>,
+    stats: Stats,
+    /// With `--watch`, the freshly fetched upstream commit to publish the
+    /// history of, rather than that of the checkout's `HEAD`.
+    tip: Option<String>,
+    /// Set by the SIGINT handler in `--watch` mode to stop after the commit
+    /// currently being processed.
+    interrupted: Arc<AtomicBool>,
+}
+
+/// Counters accumulated over a run and printed as a summary at the end.
+///
+/// These are atomics because logs are fetched in parallel.
+#[derive(Default)]
+struct Stats {
+    commits_processed: AtomicUsize,
+    commits_cached: AtomicUsize,
+    commits_skipped: AtomicUsize,
+    commits_unfinished: AtomicUsize,
+    /// Commits skipped for having more unparseable logs than
+    /// `--max-parse-failures` allows
+    commits_unparseable: AtomicUsize,
+    logs_fetched: AtomicUsize,
+    logs_cache_hit: AtomicUsize,
+    logs_failed: AtomicUsize,
+    jobs_identified: AtomicUsize,
+    jobs_unidentified: AtomicUsize,
+    bytes_uncompressed: AtomicU64,
+    bytes_compressed: AtomicU64,
+    /// Total time spent compressing commits on the thread pool, in nanoseconds
+    compress_nanos: AtomicU64,
+    /// Time spent waiting for those compressions to finish, in nanoseconds
+    compress_wait_nanos: AtomicU64,
+}
+
+/// Sizes and timing of a single `compress` call.
+struct Compressed {
+    uncompressed: u64,
+    compressed: u64,
+    elapsed: Duration,
 }
 
 struct Log {
     job_url: String,
     contents: String,
     path: String,
+    result: Option<String>,
+    /// Which build of the commit this log is from, 0 being the first.
+    run: usize,
+    /// See `Job::parents`
+    parents: Vec<String>,
+    order: Option<u32>,
 }
 
-const USAGE: &'static str = "
+/// The result of parsing a single log, cached on disk so re-runs can skip the
+/// parse entirely.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct ParsedLog {
+    name: String,
+    job: Job,
+}
+
+const USAGE: &str = "
 This is some usage
 
 Usage:
-    publish-data-to-s3 [options] <rust-repo> <cache-dir>
+    publish-data-to-s3 [options] [--timing-regex <re>]... [--skip-parser <name>]... <rust-repo> <cache-dir>
+    publish-data-to-s3 [options] preload <cache-dir> <sha>...
+    publish-data-to-s3 [options] merge-cache <src> <cache-dir>
+    publish-data-to-s3 (--add-skip <sha>)... <cache-dir>
     publish-data-to-s3 -h | --help
 
+The `preload` command only fetches and caches the raw logs of the given
+commits, so a later run can parse them without touching the network.
+
+The `merge-cache` command copies the commits and logs cached in <src>, such as
+by a backfill on another machine, into <cache-dir>. Entries already in
+<cache-dir> are left alone and any which don't decompress are skipped. The
+skip list and the manifest of published commits end up with the entries of
+both.
+
+Commits listed in `<cache-dir>/skip-list` are never processed, `--add-skip`
+appends to that list.
+
+Logs are fetched from Azure Pipelines by default, `--ci gitlab` fetches them
+from the pipelines of `--gitlab-project` instead, authenticating with the
+`GITLAB_TOKEN` environment variable if it's set. Likewise `--ci github` fetches
+them from the GitHub Actions runs of `--github-repo`, authenticating with
+`GITHUB_TOKEN`, which GitHub requires for downloading logs.
+
+The optional parsers which can be skipped are `cpu`, `test-suites`, `passes`,
+`cargo-builds`, `docker-build`, `diagnostics`, `cargo-timings`,
+`parallel-rustc`, `target-size` and `fetch`.
+
 Options:
     -h --help                    Show this screen.
+    --parser-version <n>         Version of the log parser, bump this to
+                                 invalidate cached parse results [default: 21]
+    --concurrency <n>            Maximum number of parallel network operations,
+                                 defaults to the number of CPUs
+    --s3-endpoint <url>          Custom endpoint for S3-compatible stores
+    --log-format <fmt>           Log output format, `human` or `json`
+                                 [default: human]
+    --watch <secs>               Keep running, polling for new commits at the
+                                 given interval by fetching the upstream branch
+                                 of <rust-repo>, whose checkout is left alone
+    --pretty                     Pretty-print cached commit data for debugging
+    --incremental                Only process commits newer than the last one
+                                 published, as recorded in the cache
+    --timing-regex <re>          Additional regex marking a step's timing, with
+                                 named `name` and `dur` capture groups
+    --add-skip <sha>             Add a commit to the skip list
+    --prune-unpublishable        Add commits without any timing data to the
+                                 skip list instead of caching them
+    --skip-parser <name>         Don't run the given optional parser
+    --no-cpu-detect              Don't scan logs for the CPU model, the same as
+                                 `--skip-parser cpu`
+    --ci <ci>                    CI service to fetch logs from, `azure`,
+                                 `github` or `gitlab` [default: azure]
+    --github-repo <repo>         GitHub repository whose Actions runs are
+                                 fetched [default: rust-lang/rust]
+    --gitlab-url <url>           Base URL of the GitLab instance
+                                 [default: https://gitlab.com]
+    --gitlab-project <id>        ID or URL-encoded path of the GitLab project
+    --job-name-needle <s>        Marker preceding the job's name in its log,
+                                 defaults to `[CI_JOB_NAME=`
+    --min-log-bytes <n>          Treat fetched logs shorter than this as failed
+                                 fetches rather than caching them [default: 100]
+    --max-parse-failures <pct>   Skip rather than publish a commit when more
+                                 than this percentage of its logs couldn't be
+                                 parsed, adding it to the skip list with
+                                 `--prune-unpublishable` [default: 50]
+    --delta-encode <n>           Store each commit's crate timings as changes
+                                 from the last commit stored in full, storing
+                                 every <n>th commit in full
+    --normalize-steps            Trim step names and collapse their whitespace
+                                 so trivially different names are merged
+    --lowercase-steps            Also lowercase step names when normalizing
+    --cache-readonly             Carry on without caching anything when writing
+                                 to the cache fails, for read-only caches
+    --flush-target <re>          Only attribute crates to steps whose name
+                                 matches this regex, rather than to whichever
+                                 step finishes next
+    --min-part-secs <secs>       Lump crates which took less than this long in a
+                                 step together as `below_threshold`
+                                 [default: 0]
+    --dump-raw-timings           Print every timing line of each job parsed,
+                                 for debugging the parsers
+    --connect-timeout <secs>     Give up on connecting to a server after this
+                                 long [default: 30]
+    --max-time <secs>            Give up on any single request after this long
+                                 [default: 300]
 ";
 
 #[derive(Debug, serde::Deserialize)]
 struct Args {
+    cmd_preload: bool,
+    cmd_merge_cache: bool,
+    arg_src: PathBuf,
     arg_rust_repo: PathBuf,
     arg_cache_dir: PathBuf,
+    arg_sha: Vec<String>,
+    flag_parser_version: u32,
+    flag_concurrency: Option<usize>,
+    flag_s3_endpoint: Option<String>,
+    flag_log_format: LogFormat,
+    flag_watch: Option<u64>,
+    flag_timing_regex: Vec<String>,
+    flag_incremental: bool,
+    flag_pretty: bool,
+    flag_add_skip: Vec<String>,
+    flag_prune_unpublishable: bool,
+    flag_skip_parser: Vec<String>,
+    flag_no_cpu_detect: bool,
+    flag_ci: Ci,
+    flag_github_repo: String,
+    flag_gitlab_url: String,
+    flag_gitlab_project: Option<String>,
+    flag_job_name_needle: Option<String>,
+    flag_min_log_bytes: usize,
+    flag_max_parse_failures: f64,
+    flag_delta_encode: Option<usize>,
+    flag_normalize_steps: bool,
+    flag_cache_readonly: bool,
+    flag_flush_target: Option<String>,
+    flag_dump_raw_timings: bool,
+    flag_min_part_secs: f64,
+    flag_connect_timeout: u64,
+    flag_max_time: u64,
+    flag_lowercase_steps: bool,
 }
 
-fn main() {
-    env_logger::init();
+#[derive(Debug, serde::Deserialize, Clone, Copy, PartialEq)]
+enum Ci {
+    Azure,
+    Github,
+    Gitlab,
+}
 
+/// Parsers which aren't needed to chart timings and may be skipped with
+/// `--skip-parser`.
+const OPTIONAL_PARSERS: &[&str] = &[
+    "cpu",
+    "test-suites",
+    "passes",
+    "cargo-builds",
+    "docker-build",
+    "diagnostics",
+    "cargo-timings",
+    "parallel-rustc",
+    "target-size",
+    "fetch",
+];
+
+fn main() {
     let args: Args = docopt::Docopt::new(USAGE)
         .and_then(|d| d.deserialize())
         .unwrap_or_else(|e| e.exit());
+    shared::init_logger(args.flag_log_format);
 
-    let result = Context {
-        azure: HashMap::new(),
-        cache: args.arg_cache_dir.clone(),
+    // All parallel fetching goes through rayon's global pool, so bounding its
+    // size bounds the number of outbound connections.
+    if let Some(n) = args.flag_concurrency {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(n)
+            .build_global()
+            .expect("failed to configure thread pool");
     }
-    .run(&args);
+
+    let mut cx = Context::new(&args).unwrap_or_else(|e| {
+        print_error(&e);
+        process::exit(1);
+    });
+    let result = if !args.flag_add_skip.is_empty() {
+        cx.add_skip(&args.flag_add_skip)
+    } else if args.cmd_preload {
+        cx.preload(&args.arg_sha)
+    } else if args.cmd_merge_cache {
+        cx.merge_cache(&args.arg_src)
+    } else {
+        match args.flag_watch {
+            Some(secs) => cx.watch(&args, Duration::from_secs(secs)),
+            None => cx.run(&args),
+        }
+    };
     let err = match result {
         Ok(()) => return,
         Err(e) => e,
     };
+    print_error(&err);
+    process::exit(1);
+}
+
+fn parse_timing_regex(re: &str) -> Result<Regex, Error> {
+    let regex = Regex::new(re)?;
+    for group in ["name", "dur"].iter() {
+        if !regex.capture_names().any(|n| n == Some(group)) {
+            bail!("timing regex `{}` has no `{}` capture group", re, group);
+        }
+    }
+    Ok(regex)
+}
+
+fn print_error(err: &Error) {
     eprintln!("error: {}", err);
     for cause in err.iter_causes() {
         eprintln!("\tcaused by: {}", cause);
     }
-    process::exit(1);
 }
 
 impl Context {
+    fn new(args: &Args) -> Result<Context, Error> {
+        let timing_regexes = args
+            .flag_timing_regex
+            .iter()
+            .map(|re| parse_timing_regex(re))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut skip_parsers = HashSet::new();
+        for name in args.flag_skip_parser.iter() {
+            if !OPTIONAL_PARSERS.contains(&name.as_str()) {
+                bail!(
+                    "unknown parser `{}`, expected one of: {}",
+                    name,
+                    OPTIONAL_PARSERS.join(", ")
+                );
+            }
+            skip_parsers.insert(name.clone());
+        }
+        if args.flag_no_cpu_detect {
+            skip_parsers.insert("cpu".to_string());
+        }
+
+        if args.flag_ci == Ci::Gitlab && args.flag_gitlab_project.is_none() {
+            bail!("`--ci gitlab` requires `--gitlab-project`");
+        }
+
+        Ok(Context {
+            azure: HashMap::new(),
+            azure_continuation: None,
+            azure_exhausted: false,
+            unfinished: HashSet::new(),
+            gitlab: HashMap::new(),
+            github: HashMap::new(),
+            ci: args.flag_ci,
+            github_repo: args.flag_github_repo.clone(),
+            gitlab_url: args.flag_gitlab_url.trim_end_matches('/').to_string(),
+            gitlab_project: args.flag_gitlab_project.clone(),
+            job_name_needle: args
+                .flag_job_name_needle
+                .clone()
+                .unwrap_or_else(|| "[CI_JOB_NAME=".to_string()),
+            min_log_bytes: args.flag_min_log_bytes,
+            max_parse_failures: args.flag_max_parse_failures,
+            delta_every: args.flag_delta_encode,
+            delta_base: None,
+            cache: args.arg_cache_dir.clone(),
+            cache_readonly: args.flag_cache_readonly,
+            parser_version: args.flag_parser_version,
+            s3_endpoint: args.flag_s3_endpoint.clone(),
+            connect_timeout: args.flag_connect_timeout,
+            max_time: args.flag_max_time,
+            pretty: args.flag_pretty,
+            timing_regexes,
+            min_part_secs: args.flag_min_part_secs,
+            flush_target: args
+                .flag_flush_target
+                .as_deref()
+                .map(Regex::new)
+                .transpose()?,
+            skip_parsers,
+            normalize_steps: args.flag_normalize_steps || args.flag_lowercase_steps,
+            lowercase_steps: args.flag_lowercase_steps,
+            dump_raw_timings: args.flag_dump_raw_timings,
+            pending_writes: Mutex::new(Vec::new()),
+            stats: Stats::default(),
+            tip: None,
+            interrupted: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    /// Repeatedly fetches the rust repo and runs a publish pass over any new
+    /// commits, sleeping for `interval` in between, until interrupted.
+    ///
+    /// Errors in a pass are reported but otherwise ignored, assuming they're
+    /// transient network problems.
+    fn watch(&mut self, args: &Args, interval: Duration) -> Result<(), Error> {
+        let interrupted = self.interrupted.clone();
+        ctrlc::set_handler(move || interrupted.store(true, Ordering::SeqCst))?;
+
+        while !self.interrupted.load(Ordering::SeqCst) {
+            if let Err(e) = self
+                .fetch(&args.arg_rust_repo)
+                .and_then(|()| self.run(args))
+            {
+                print_error(&e);
+            }
+            // Builds are only listed once per pass, so start fresh next time
+            self.azure.clear();
+            self.azure_continuation = None;
+            self.azure_exhausted = false;
+            self.unfinished.clear();
+            self.gitlab.clear();
+            self.github.clear();
+            self.stats = Stats::default();
+
+            let start = Instant::now();
+            while start.elapsed() < interval && !self.interrupted.load(Ordering::SeqCst) {
+                thread::sleep(Duration::from_millis(500));
+            }
+        }
+        println!("interrupted, exiting");
+        Ok(())
+    }
+
+    /// Fetches the upstream branch of `repo` for the next pass to publish,
+    /// without touching whatever's checked out there.
+    fn fetch(&mut self, repo: &Path) -> Result<(), Error> {
+        let status = Command::new("git")
+            .arg("fetch")
+            .arg("--quiet")
+            .current_dir(repo)
+            .status()?;
+        if !status.success() {
+            bail!("failed to fetch {:?}: {}", repo, status);
+        }
+        self.tip = Some(shared::resolve_git_sha(repo, "@{upstream}")?);
+        Ok(())
+    }
+
+    /// The commits to publish, newest first.
+    fn git_commits(
+        &self,
+        repo: &Path,
+    ) -> Result<impl Iterator<Item = Result<GitCommit, Error>>, Error> {
+        let rev = self.tip.as_deref().unwrap_or("HEAD");
+        shared::get_git_commits_from(repo, rev, Some("bors"))
+    }
+
     fn run(&mut self, args: &Args) -> Result<(), Error> {
-        for commit in shared::get_git_commits(&args.arg_rust_repo)? {
+        // The newest commit with published data is recorded so that
+        // `--incremental` runs can stop there without asking S3 about every
+        // commit along the way.
+        let marker = self.cache.join(".last-published");
+        let last_published = if args.flag_incremental && marker.exists() {
+            Some(fs::read_to_string(&marker)?.trim().to_string())
+        } else {
+            None
+        };
+        let mut cached = Vec::new();
+        let result = self.cache_commits(args, last_published.as_deref(), &mut cached);
+        // Commits aren't cached until they're written out, so that has to
+        // finish before recording any as published.
+        self.finish_writes()?;
+        if !cached.is_empty() {
+            self.update_manifest(&args.arg_rust_repo, cached)?;
+        }
+        if let Some(newest) = result? {
+            self.cache_write(&marker, || Ok(fs::write(&marker, newest)?))?;
+        }
+        self.stats.print();
+        Ok(())
+    }
+
+    /// Caches all commits newer than the last published one, returning the
+    /// newest commit which is now cached or published.
+    ///
+    /// Each commit now cached is added to `cached`.
+    fn cache_commits(
+        &mut self,
+        args: &Args,
+        last_published: Option<&str>,
+        cached: &mut Vec<ManifestEntry>,
+    ) -> Result<Option<String>, Error> {
+        let skip = self.skip_list()?;
+        let mut newest = None;
+        for commit in self.git_commits(&args.arg_rust_repo)? {
+            if self.interrupted.load(Ordering::SeqCst) {
+                break;
+            }
             let commit = commit?;
-            if self.exists_on_s3(&commit.sha) {
+            if skip.contains(&commit.sha) {
+                log::debug!("skipping {}, it's in the skip list", commit.sha);
+                continue;
+            }
+            let published = match last_published {
+                Some(sha) => commit.sha == sha,
+                None => self.exists_on_s3(&commit.sha),
+            };
+            if published {
+                newest.get_or_insert(commit.sha);
                 break;
             }
-            self.cache_commit(&commit.sha)?;
+            self.stats.commits_processed.fetch_add(1, Ordering::SeqCst);
+            if self.cache_commit(&commit.sha, args.flag_prune_unpublishable)? {
+                cached.push(ManifestEntry::new(&commit));
+                if newest.is_none() {
+                    newest = Some(commit.sha.clone());
+                }
+            }
             if commit.sha == "3849a5f83b82258fd76a3ff64933b81d7efeffa1" {
                 break;
             }
         }
+        Ok(newest)
+    }
+
+    /// Adds `new` to the manifest of all published commits, which
+    /// `build-site --from-manifest` reads in place of a git checkout.
+    fn update_manifest(&self, repo: &Path, new: Vec<ManifestEntry>) -> Result<(), Error> {
+        let path = self.cache.join(shared::MANIFEST);
+        let mut entries = if path.exists() {
+            serde_json::from_slice(&fs::read(&path)?)?
+        } else {
+            // The cache may well be fresh, with the full manifest only on S3
+            let s3_url = shared::s3_url(self.s3_endpoint.as_deref());
+            match shared::download_manifest(&s3_url)? {
+                Some(entries) => entries,
+                None => self.backfill_manifest(repo)?,
+            }
+        };
+        merge_manifest(&mut entries, new);
+        self.write_manifest(&entries)
+    }
+
+    fn write_manifest(&self, entries: &[ManifestEntry]) -> Result<(), Error> {
+        let path = self.cache.join(shared::MANIFEST);
+        self.cache_write(&path, || {
+            fs::create_dir_all(path.parent().unwrap())?;
+            fs::write(&path, serde_json::to_string(entries)?)?;
+            Ok(())
+        })
+    }
+
+    /// Starts off a manifest with every commit published before there was
+    /// one, dated from the git history of `repo`.
+    ///
+    /// This needs to list the bucket, without which the manifest only covers
+    /// commits published from now on.
+    fn backfill_manifest(&self, repo: &Path) -> Result<Vec<ManifestEntry>, Error> {
+        let s3_url = shared::s3_url(self.s3_endpoint.as_deref());
+        let published = match shared::list_published_commits(&s3_url) {
+            Ok(shas) => shas.into_iter().collect::<HashSet<_>>(),
+            Err(e) => {
+                log::warn!(
+                    "failed to list published commits, the manifest will \
+                     only list those published from now on: {}",
+                    e
+                );
+                return Ok(Vec::new());
+            }
+        };
+        let mut entries = Vec::new();
+        for commit in self.git_commits(repo)? {
+            let commit = commit?;
+            if published.contains(&commit.sha) {
+                entries.push(ManifestEntry::new(&commit));
+            }
+        }
+        log::info!("backfilled the manifest with {} commits", entries.len());
+        Ok(entries)
+    }
+
+    fn skip_list(&self) -> Result<HashSet<String>, Error> {
+        read_skip_list(&self.cache.join("skip-list"))
+    }
+
+    fn add_skip(&self, shas: &[String]) -> Result<(), Error> {
+        let existing = self.skip_list()?;
+        fs::create_dir_all(&self.cache)?;
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.cache.join("skip-list"))?;
+        for sha in shas {
+            let sha = shared::resolve_sha(&self.cache, sha)?;
+            if !existing.contains(&sha) {
+                writeln!(file, "{}", sha)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn preload(&mut self, shas: &[String]) -> Result<(), Error> {
+        for sha in shas {
+            let sha = shared::resolve_sha(&self.cache, sha)?;
+            self.stats.commits_processed.fetch_add(1, Ordering::SeqCst);
+            match self.logs(&sha)? {
+                Some(logs) => println!("cached {} logs for {}", logs.len(), sha),
+                None => {
+                    println!("skipping {}, it has no finished build", sha);
+                    self.stats.commits_unfinished.fetch_add(1, Ordering::SeqCst);
+                }
+            }
+        }
+        self.stats.print();
+        Ok(())
+    }
+
+    fn merge_cache(&self, src: &Path) -> Result<(), Error> {
+        let mut merged = Merged::default();
+        for dir in ["commits", "logs"].iter() {
+            merge_dir(&src.join(dir), &self.cache.join(dir), &mut merged)?;
+        }
+        println!(
+            "merged {} entries, {} were already cached and {} were corrupt",
+            merged.copied, merged.existing, merged.corrupt
+        );
+
+        // Unlike the entries, these are lists which both caches add to
+        let skip = self.skip_list()?;
+        let mut skips = read_skip_list(&src.join("skip-list"))?
+            .into_iter()
+            .filter(|sha| !skip.contains(sha))
+            .collect::<Vec<_>>();
+        skips.sort();
+        if !skips.is_empty() {
+            self.add_skip(&skips)?;
+        }
+        let manifest = src.join(shared::MANIFEST);
+        let mut manifest_entries = 0;
+        if manifest.exists() {
+            let path = self.cache.join(shared::MANIFEST);
+            let mut entries: Vec<ManifestEntry> = if path.exists() {
+                serde_json::from_slice(&fs::read(&path)?)?
+            } else {
+                Vec::new()
+            };
+            let before = entries.len();
+            merge_manifest(&mut entries, serde_json::from_slice(&fs::read(&manifest)?)?);
+            manifest_entries = entries.len() - before;
+            self.write_manifest(&entries)?;
+        }
+        println!(
+            "merged {} skip list entries and {} manifest entries",
+            skips.len(),
+            manifest_entries
+        );
         Ok(())
     }
 
@@ -81,84 +679,786 @@ impl Context {
             .is_ok()
     }
 
-    fn cache_commit(&mut self, commit: &str) -> Result<(), Error> {
+    /// Parses all the logs of `commit` into the cache, returning whether its
+    /// data is now cached.
+    ///
+    /// With `prune` set, commits without any jobs are added to the skip list
+    /// rather than being cached.
+    fn cache_commit(&mut self, commit: &str, prune: bool) -> Result<bool, Error> {
         log::debug!("learning about {}", commit);
         let dir = self.cache.join("commits");
         let dst = dir.join(commit).with_extension("json.gz");
         if dst.exists() {
-            return Ok(());
+            self.stats.commits_skipped.fetch_add(1, Ordering::SeqCst);
+            return Ok(true);
         }
+        let hits = self.stats.logs_cache_hit.load(Ordering::SeqCst);
+        let fetches = self.stats.logs_fetched.load(Ordering::SeqCst);
         let logs = self.logs(commit)?;
-        fs::create_dir_all(dst.parent().unwrap())?;
+        let hits = self.stats.logs_cache_hit.load(Ordering::SeqCst) - hits;
+        let fetches = self.stats.logs_fetched.load(Ordering::SeqCst) - fetches;
+        if hits + fetches > 0 {
+            log::info!(
+                "{}: {} logs from cache, {} fetched ({:.0}% hit rate)",
+                commit,
+                hits,
+                fetches,
+                hit_rate(hits, fetches)
+            );
+        }
+        let logs = match logs {
+            Some(logs) => logs,
+            None => {
+                println!("skipping {}, it has no finished build", commit);
+                self.stats.commits_unfinished.fetch_add(1, Ordering::SeqCst);
+                return Ok(false);
+            }
+        };
+        self.cache_write(&dir, || Ok(fs::create_dir_all(&dir)?))?;
 
         let mut meta = Commit::default();
+        match self.ci {
+            Ci::Azure => {
+                let build = &self.azure[commit][0];
+                meta.build_number = Some(build.build_number.clone());
+                meta.queue_time = build.queue_time.clone();
+                meta.start_time = build.start_time.clone();
+                meta.finish_time = build.finish_time.clone();
+                meta.build_dur = build.duration();
+            }
+            Ci::Gitlab => {
+                let pipeline = &self.gitlab[commit][0];
+                meta.build_number = Some(pipeline.iid.to_string());
+                meta.queue_time = Some(pipeline.created_at.clone());
+                meta.start_time = pipeline.started_at.clone();
+                meta.finish_time = pipeline.finished_at.clone();
+                meta.build_dur = pipeline.duration;
+            }
+            Ci::Github => {
+                let run = &self.github[commit][0];
+                meta.build_number = Some(run.run_number.to_string());
+                meta.queue_time = Some(run.created_at.clone());
+                meta.start_time = run.run_started_at.clone();
+                meta.finish_time = Some(run.updated_at.clone());
+                meta.build_dur = run.duration();
+            }
+        }
 
+        let mut failures = 0;
         for log in logs.iter() {
-            let job = match self.identify_job(log) {
-                Ok(s) => s,
-                Err(_) => continue,
-            };
-            meta.jobs.insert(
-                job,
-                Job {
-                    url: log.job_url.clone(),
-                    path: log.path.clone(),
-                    cpu_microarch: self.extract_cpu_microarch(&log.contents),
-                    timings: self.extract_timings(&log.contents),
-                },
-            );
+            match self.parse_log(log)? {
+                Some(parsed) => {
+                    self.stats.jobs_identified.fetch_add(1, Ordering::SeqCst);
+                    let jobs = if log.run == 0 {
+                        &mut meta.jobs
+                    } else {
+                        while meta.reruns.len() < log.run {
+                            meta.reruns.push(BTreeMap::new());
+                        }
+                        &mut meta.reruns[log.run - 1]
+                    };
+                    let job = match jobs.remove(&parsed.name) {
+                        Some(prev) => merge_retried(prev, parsed.job),
+                        None => parsed.job,
+                    };
+                    jobs.insert(parsed.name, job);
+                }
+                None => {
+                    failures += 1;
+                    self.stats.jobs_unidentified.fetch_add(1, Ordering::SeqCst);
+                }
+            }
         }
-        let json = serde_json::to_string(&meta)?;
-        let mut raw = Vec::new();
-        let mut gz = flate2::write::GzEncoder::new(&mut raw, flate2::Compression::best());
-        gz.write_all(json.as_bytes())?;
-        gz.finish()?;
-        fs::write(&dst, raw)?;
-        Ok(())
+        // If most logs fail to parse the format has probably changed under
+        // us, and publishing would only publish garbage.
+        if !logs.is_empty() {
+            let pct = failures as f64 / logs.len() as f64 * 100.0;
+            if pct > self.max_parse_failures {
+                log::warn!(
+                    "skipping {}, {} of {} logs failed to parse, more than the {}% allowed",
+                    commit,
+                    failures,
+                    logs.len(),
+                    self.max_parse_failures
+                );
+                self.stats
+                    .commits_unparseable
+                    .fetch_add(1, Ordering::SeqCst);
+                if prune {
+                    let skip_list = self.cache.join("skip-list");
+                    self.cache_write(&skip_list, || self.add_skip(&[commit.to_string()]))?;
+                }
+                return Ok(false);
+            }
+        }
+        if prune && meta.jobs.is_empty() {
+            println!("adding {} to the skip list, it has no timing data", commit);
+            let skip_list = self.cache.join("skip-list");
+            self.cache_write(&skip_list, || self.add_skip(&[commit.to_string()]))?;
+            return Ok(false);
+        }
+        let meta = self.maybe_delta_encode(commit, meta);
+        let pretty = self.pretty;
+        // Compressing at the best level takes long enough to be worth
+        // overlapping with fetching and parsing the next commit. The JSON is
+        // streamed straight into the encoder rather than buffered first.
+        let (tx, rx) = mpsc::channel();
+        rayon::spawn(move || {
+            let written = compress(&dst, |w| {
+                if pretty {
+                    serde_json::to_writer_pretty(w, &meta)?;
+                } else {
+                    serde_json::to_writer(w, &meta)?;
+                }
+                Ok(())
+            });
+            drop(tx.send(written));
+        });
+        self.pending_writes.lock().unwrap().push(rx);
+        self.stats.commits_cached.fetch_add(1, Ordering::SeqCst);
+        Ok(true)
+    }
+
+    /// Waits for all commits queued by `cache_commit` to be written out.
+    fn finish_writes(&self) -> Result<(), Error> {
+        let start = Instant::now();
+        let mut result = Ok(());
+        let pending = std::mem::take(&mut *self.pending_writes.lock().unwrap());
+        for rx in pending {
+            match rx.recv()? {
+                Ok(compressed) => {
+                    self.stats
+                        .compress_nanos
+                        .fetch_add(compressed.elapsed.as_nanos() as u64, Ordering::SeqCst);
+                    self.stats.record_compressed(&compressed);
+                }
+                Err(e) if self.cache_readonly => {
+                    log::warn!("failed to cache commit: {}", e);
+                }
+                Err(e) => result = Err(e),
+            }
+        }
+        self.stats
+            .compress_wait_nanos
+            .fetch_add(start.elapsed().as_nanos() as u64, Ordering::SeqCst);
+        result
+    }
+
+    /// With `--delta-encode`, encodes `meta` against the last commit cached in
+    /// full, unless it's time for another full one.
+    fn maybe_delta_encode(&mut self, commit: &str, mut meta: Commit) -> Commit {
+        let every = match self.delta_every {
+            Some(every) => every,
+            None => return meta,
+        };
+        match &mut self.delta_base {
+            Some((base_sha, base, count)) if *count < every => {
+                shared::delta_encode(&mut meta, base_sha, base);
+                *count += 1;
+            }
+            _ => self.delta_base = Some((commit.to_string(), meta.clone(), 1)),
+        }
+        meta
+    }
+
+    /// Parses `log` into a `Job`, returning `None` if the job couldn't be
+    /// identified.
+    ///
+    /// Results are cached in `jobs/v<parser-version>/` keyed by a hash of the
+    /// log's contents and the options affecting parsing, so reprocessing
+    /// already-parsed logs is cheap.
+    fn parse_log(&self, log: &Log) -> Result<Option<ParsedLog>, Error> {
+        // Each option is length-prefixed so no two sets of them run together
+        let mut key = Vec::new();
+        let mut add = |bytes: &[u8]| {
+            key.extend_from_slice(&(bytes.len() as u64).to_le_bytes());
+            key.extend_from_slice(bytes);
+        };
+        add(log.contents.as_bytes());
+        for re in self.timing_regexes.iter() {
+            add(re.as_str().as_bytes());
+        }
+        match &self.flush_target {
+            Some(re) => add(re.as_str().as_bytes()),
+            None => add(&[]),
+        }
+        add(&self.min_part_secs.to_bits().to_le_bytes());
+        let mut skipped = self.skip_parsers.iter().collect::<Vec<_>>();
+        skipped.sort();
+        for parser in skipped {
+            add(parser.as_bytes());
+        }
+        add(&[self.normalize_steps as u8, self.lowercase_steps as u8]);
+        add(self.job_name_needle.as_bytes());
+        let dst = self
+            .cache
+            .join("jobs")
+            .join(format!("v{}", self.parser_version))
+            .join(format!("{:016x}.json", shared::stable_hash(&key)));
+        // Dumping the raw lines needs the log to actually be parsed again
+        if dst.exists() && !self.dump_raw_timings {
+            log::debug!("using cached parse {:?}", dst);
+            let file = io::BufReader::new(fs::File::open(&dst)?);
+            let mut parsed: ParsedLog = serde_json::from_reader(file)?;
+            parsed.job.url = log.job_url.clone();
+            parsed.job.path = log.path.clone();
+            parsed.job.result = log.result.clone();
+            parsed.job.parents = log.parents.clone();
+            parsed.job.order = log.order;
+            return Ok(Some(parsed));
+        }
+
+        let name = match self.identify_job(log) {
+            Ok(s) => s,
+            Err(_) => return Ok(None),
+        };
+        // Colored output would otherwise hide markers like `[TIMING] `
+        let contents = &*strip_ansi(&log.contents);
+        if self.dump_raw_timings {
+            println!("raw timings of {} ({}):", name, log.job_url);
+        }
+        let (warnings, errors) = if self.parser_enabled("diagnostics") {
+            self.count_diagnostics(contents)
+        } else {
+            (0, 0)
+        };
+        let mut job = Job {
+            url: log.job_url.clone(),
+            path: log.path.clone(),
+            cpu_microarch: None,
+            result: log.result.clone(),
+            retries: 0,
+            unit: shared::TIMING_UNIT.to_string(),
+            timings: self.extract_timings(contents),
+            test_suites: BTreeMap::new(),
+            passes: BTreeMap::new(),
+            cargo_builds: Vec::new(),
+            docker_build: None,
+            cargo_units: BTreeMap::new(),
+            warnings,
+            errors,
+            parents: log.parents.clone(),
+            order: log.order,
+            parallel: None,
+            fetch: None,
+        };
+        if self.parser_enabled("cpu") {
+            job.cpu_microarch = self.extract_cpu_microarch(contents);
+        }
+        if self.parser_enabled("test-suites") {
+            job.test_suites = self.extract_test_suites(contents);
+        }
+        if self.parser_enabled("passes") {
+            job.passes = self.extract_time_passes(contents);
+        }
+        if self.parser_enabled("cargo-builds") {
+            job.cargo_builds = self.extract_cargo_builds(contents);
+        }
+        if self.parser_enabled("docker-build") {
+            job.docker_build = self.extract_docker_build(contents);
+        }
+        if self.parser_enabled("cargo-timings") {
+            job.cargo_units = self.extract_cargo_timings(contents);
+        }
+        if self.parser_enabled("parallel-rustc") {
+            job.parallel = self.extract_parallelism(contents);
+        }
+        if self.parser_enabled("fetch") {
+            job.fetch = self.extract_fetch(contents);
+        }
+        let parsed = ParsedLog { name, job };
+        self.cache_write(&dst, || {
+            fs::create_dir_all(dst.parent().unwrap())?;
+            let mut file = io::BufWriter::new(fs::File::create(&dst)?);
+            serde_json::to_writer(&mut file, &parsed)?;
+            file.flush()?;
+            Ok(())
+        })?;
+        Ok(Some(parsed))
     }
 
     fn extract_timings(&self, contents: &str) -> BTreeMap<String, Timing> {
         let mut ret = BTreeMap::new();
+        // Crates compiled since the last step, summed in integer nanoseconds
+        // so thousands of tiny durations don't accumulate rounding errors.
         let mut parts = HashMap::new();
-        for line in contents.lines() {
-            let line = line.trim();
-            if let Some(rest) = find_get_after(line, "[RUSTC-TIMING] ") {
-                let mut iter = rest.rsplitn(2, ' ');
-                if let Ok(time) = iter.next().unwrap().parse::<f64>() {
-                    let name = iter.next().unwrap();
-                    *parts.entry(name.to_string()).or_insert(0.0) += time;
+        // The parts of each step, only converted to seconds once they're all
+        // known.
+        let mut step_parts = HashMap::new();
+        // Azure prefixes every line with a timestamp which gives the offset of
+        // each step within the job, but if that's missing fall back to
+        // assuming steps run back to back.
+        let job_start = contents.lines().find_map(line_timestamp);
+        let mut cumulative = 0.0;
+        let mut last_step = None;
+        // The same crates take very different times to check, build or
+        // document, so steps are tagged with the cargo command run in them.
+        let mut command = None;
+        let mut target_size = None;
+        let sizes = self.parser_enabled("target-size");
+        for raw_line in contents.lines() {
+            let line = raw_line.trim();
+            if let Some(c) = cargo_command(line) {
+                command = Some(c);
+            }
+            if sizes {
+                if let Some(size) = du_size(strip_timestamp(line)) {
+                    target_size = Some(size);
+                }
+            }
+            if self.dump_raw_timings
+                && (line.contains("[RUSTC-TIMING] ") || self.find_step(line).is_some())
+            {
+                println!("    {}", line);
+            }
+            // Rustc's own line may be echoed inside another, but it's always
+            // the last marker which is followed by the crate and its time.
+            if let Some(rest) = find_get_after_last(line, "[RUSTC-TIMING] ") {
+                // Resource usage may follow the time, see `extract_parallelism`
+                let rest = rest.split(" user: ").next().unwrap();
+                let timing = rest
+                    .rsplit_once(' ')
+                    .and_then(|(name, time)| Some((name, parse_duration(time)?)));
+                if let Some((name, time)) = timing {
+                    if let Some(time) = validate_duration(time, line) {
+                        let (total, count) = parts.entry(name.to_string()).or_insert((0u64, 0));
+                        *total += secs_to_nanos(time);
+                        *count += 1;
+                    }
                 }
             }
 
-            if let Some(rest) = find_get_after(line, "[TIMING] ") {
-                let pos = match rest.find(" -- ") {
-                    Some(i) => i,
+            if let Some((step, dur)) = self.find_step(line) {
+                let dur = match validate_duration(dur, line) {
+                    Some(dur) => dur,
                     None => continue,
                 };
-                let step = &rest[..pos];
-                let dur = rest[pos + 4..].parse::<f64>().unwrap();
-                let timing = ret.entry(step.to_string()).or_insert_with(Timing::default);
+                let end = job_start.and_then(|start| {
+                    let time = line_timestamp(raw_line)?;
+                    Some((time - start).num_milliseconds() as f64 / 1000.0)
+                });
+                let offset = match end {
+                    Some(end) => (end - dur).max(0.0),
+                    None => cumulative,
+                };
+                cumulative += dur;
+                let step = self.normalize_step(step);
+                let timing = ret.entry(step.clone()).or_insert_with(Timing::default);
+                timing.offset.get_or_insert(offset);
                 timing.dur += dur;
-                for (k, v) in parts.drain() {
-                    *timing.parts.entry(k).or_insert(0.0) += v;
+                if let Some(command) = command.take() {
+                    timing.cargo_command = Some(command.to_string());
+                }
+                if let Some(size) = target_size.take() {
+                    timing.target_size = Some(size);
+                }
+                // Otherwise crates are held until a targeted step so they
+                // don't leak into unrelated steps like documenting.
+                let targeted = match &self.flush_target {
+                    Some(re) => re.is_match(&step),
+                    None => true,
+                };
+                if targeted {
+                    flush_parts(&mut parts, step_parts.entry(step.clone()).or_default());
+                    last_step = Some(step);
+                }
+            }
+        }
+
+        // Crates compiled after the last (targeted) step finished would
+        // otherwise be lost, so attribute them to that step, or if there were
+        // no such steps at all to one standing in for the whole job.
+        if !parts.is_empty() {
+            let step = match last_step {
+                Some(step) => step,
+                None => {
+                    let timing = ret
+                        .entry(UNATTRIBUTED_STEP.to_string())
+                        .or_insert_with(Timing::default);
+                    timing.dur = nanos_to_secs(parts.values().map(|(total, _)| total).sum());
+                    timing.cargo_command = command.map(|c| c.to_string());
+                    UNATTRIBUTED_STEP.to_string()
                 }
+            };
+            flush_parts(&mut parts, step_parts.entry(step).or_default());
+        }
+        for (step, mut parts) in step_parts {
+            if self.min_part_secs > 0.0 {
+                lump_small_parts(&mut parts, secs_to_nanos(self.min_part_secs));
+            }
+            let timing = ret.get_mut(&step).unwrap();
+            for (k, (total, count)) in parts {
+                timing.parts.insert(k.clone(), nanos_to_secs(total));
+                timing.part_counts.insert(k, count);
             }
         }
-        return ret;
+        ret
+    }
+
+    /// Applies `--normalize-steps` to a step's name.
+    fn normalize_step(&self, step: &str) -> String {
+        if !self.normalize_steps {
+            return step.to_string();
+        }
+        let step = step.split_whitespace().collect::<Vec<_>>().join(" ");
+        if self.lowercase_steps {
+            step.to_lowercase()
+        } else {
+            step
+        }
+    }
+
+    /// Finds a step's name and duration in `line`, either from the built-in
+    /// `[TIMING] <step> -- <dur>` marker or from any `--timing-regex`.
+    fn find_step<'a>(&self, line: &'a str) -> Option<(&'a str, f64)> {
+        if let Some(rest) = find_get_after(line, "[TIMING] ") {
+            let pos = rest.find(" -- ")?;
+            return Some((&rest[..pos], parse_duration(&rest[pos + 4..])?));
+        }
+        self.timing_regexes.iter().find_map(|re| {
+            let caps = re.captures(line)?;
+            let dur = parse_duration(caps.name("dur")?.as_str())?;
+            Some((caps.name("name")?.as_str(), dur))
+        })
+    }
+
+    /// Compiletest prints a `Check compiletest suite=<name> ...` header
+    /// before each suite and a `test result: ... finished in <secs>s` summary
+    /// after it, so pair the two up to get per-suite durations.
+    fn extract_test_suites(&self, contents: &str) -> BTreeMap<String, f64> {
+        let mut ret = BTreeMap::new();
+        let mut suite = None;
+        for line in contents.lines() {
+            let line = line.trim();
+            if let Some(rest) = find_get_after(line, "compiletest suite=") {
+                suite = rest.split_whitespace().next();
+                continue;
+            }
+            let name = match suite {
+                Some(name) => name,
+                None => continue,
+            };
+            if !line.contains("test result: ") {
+                continue;
+            }
+            // Test names may themselves say "finished in", but the time is
+            // always at the end.
+            if let Some(rest) = find_get_after_last(line, "finished in ") {
+                if let Ok(dur) = rest.trim_end_matches('s').parse::<f64>() {
+                    *ret.entry(name.to_string()).or_insert(0.0) += dur;
+                }
+            }
+            suite = None;
+        }
+        ret
+    }
+
+    /// Parses `-Z time-passes` output, lines of the form
+    /// `time: <wall>; user: <user>; sys: <system>; ...\t<pass>`, where only
+    /// the wall time is required.
+    fn extract_time_passes(&self, contents: &str) -> BTreeMap<String, Timing> {
+        let mut ret = BTreeMap::new();
+        for line in contents.lines() {
+            let line = strip_timestamp(line.trim());
+            if !line.starts_with("time:") {
+                continue;
+            }
+            let mut iter = line.rsplitn(2, '\t');
+            let name = iter.next().unwrap().trim();
+            let fields = match iter.next() {
+                Some(fields) => fields,
+                None => continue,
+            };
+            let mut wall = None;
+            let mut user = None;
+            let mut system = None;
+            for field in fields.split(';') {
+                let mut kv = field.splitn(2, ':');
+                let key = kv.next().unwrap().trim();
+                let value = kv
+                    .next()
+                    .and_then(|v| v.split_whitespace().next())
+                    .and_then(|v| v.parse::<f64>().ok())
+                    .and_then(|v| validate_duration(v, line));
+                match key {
+                    "time" => wall = value,
+                    "user" => user = value,
+                    "sys" | "system" => system = value,
+                    _ => {}
+                }
+            }
+            let wall = match wall {
+                Some(wall) => wall,
+                None => continue,
+            };
+            let timing = ret.entry(name.to_string()).or_insert_with(Timing::default);
+            timing.dur += wall;
+            if let Some(user) = user {
+                *timing.user.get_or_insert(0.0) += user;
+            }
+            if let Some(system) = system {
+                *timing.system.get_or_insert(0.0) += system;
+            }
+        }
+        ret
+    }
+
+    /// Parses cargo's summary line at the end of each build, which looks like
+    /// `Finished release [optimized] target(s) in 1m 23s` (or, in newer
+    /// versions, ``Finished `release` profile [optimized] ...``).
+    fn extract_cargo_builds(&self, contents: &str) -> Vec<CargoBuild> {
+        let mut ret = Vec::new();
+        for line in contents.lines() {
+            let rest = match find_get_after(line.trim(), "Finished ") {
+                Some(rest) => rest,
+                None => continue,
+            };
+            let pos = match rest.find(" target(s) in ") {
+                Some(pos) => pos,
+                None => continue,
+            };
+            let profile = rest[..pos]
+                .split(" [")
+                .next()
+                .unwrap()
+                .trim_end_matches(" profile")
+                .trim_matches('`');
+            let dur = match parse_elapsed(&rest[pos + " target(s) in ".len()..]) {
+                Some(dur) => dur,
+                None => continue,
+            };
+            if let Some(dur) = validate_duration(dur, line) {
+                ret.push(CargoBuild {
+                    profile: profile.to_string(),
+                    dur,
+                });
+            }
+        }
+        ret
+    }
+
+    /// Times a BuildKit docker build, which prints `#<step> DONE <secs>s` as
+    /// each step completes.
+    ///
+    /// BuildKit runs independent steps in parallel, so with Azure's
+    /// timestamps this is the span from the first step starting to the last
+    /// one finishing. Without them the best there is is the sum of the steps.
+    fn extract_docker_build(&self, contents: &str) -> Option<f64> {
+        let mut total = None;
+        // The earliest start and latest finish of the steps, which are only
+        // of use if every one of them is timestamped
+        let mut span = None;
+        let mut untimed = false;
+        for line in contents.lines() {
+            let line = line.trim();
+            let time = line_timestamp(line);
+            let line = strip_timestamp(line);
+            let rest = match find_get_after(line, "#") {
+                Some(rest) => rest,
+                None => continue,
+            };
+            let mut parts = rest.split_whitespace();
+            let step = parts.next().unwrap_or("");
+            if step.is_empty() || !step.bytes().all(|b| b.is_ascii_digit()) {
+                continue;
+            }
+            if parts.next() != Some("DONE") {
+                continue;
+            }
+            let dur = parts
+                .next()
+                .and_then(|d| d.strip_suffix('s'))
+                .and_then(|d| d.parse::<f64>().ok())
+                .and_then(|d| validate_duration(d, line));
+            let dur = match dur {
+                Some(dur) => dur,
+                None => continue,
+            };
+            *total.get_or_insert(0.0) += dur;
+            let finish = match time {
+                Some(time) => time,
+                None => {
+                    untimed = true;
+                    continue;
+                }
+            };
+            let start = finish - chrono::Duration::milliseconds((dur * 1000.0) as i64);
+            span = Some(match span {
+                Some((first, last)) => (std::cmp::min(first, start), std::cmp::max(last, finish)),
+                None => (start, finish),
+            });
+        }
+        match span {
+            Some((start, finish)) if !untimed => {
+                Some((finish - start).num_milliseconds() as f64 / 1000.0)
+            }
+            _ => total,
+        }
+    }
+
+    /// Sums up the Azure sections which check out the repository or its
+    /// submodules, timed by the timestamps of their `##[section]Starting: `
+    /// and `##[section]Finishing: ` lines, so a slow clone isn't mistaken for
+    /// slow compilation.
+    fn extract_fetch(&self, contents: &str) -> Option<f64> {
+        let mut ret = None;
+        let mut started = HashMap::new();
+        for line in contents.lines() {
+            let line = line.trim();
+            let time = match line_timestamp(line) {
+                Some(time) => time,
+                None => continue,
+            };
+            let line = strip_timestamp(line);
+            if let Some(name) = line.strip_prefix("##[section]Starting: ") {
+                if is_fetch_section(name) {
+                    started.insert(name, time);
+                }
+            } else if let Some(name) = line.strip_prefix("##[section]Finishing: ") {
+                if let Some(start) = started.remove(name) {
+                    let dur = (time - start).num_milliseconds() as f64 / 1000.0;
+                    if let Some(dur) = validate_duration(dur, line) {
+                        *ret.get_or_insert(0.0) += dur;
+                    }
+                }
+            }
+        }
+        ret
+    }
+
+    /// With `RUSTC_PRINT_STEP_RUSAGE` set bootstrap appends the resource
+    /// usage of each crate to its `[RUSTC-TIMING]` line, like
+    /// `user: 1.200 sys: 0.100 max rss (kb): 1234 ... voluntary ctxt switches: 5`,
+    /// which for crates compiled with `-Z threads=<n>` shows how parallel the
+    /// front-end actually was.
+    fn extract_parallelism(&self, contents: &str) -> Option<Parallelism> {
+        let threads_re = Regex::new(r#"-Z"?\s*"?threads=(\d+)"#).unwrap();
+        let mut ret = Parallelism::default();
+        for line in contents.lines() {
+            for caps in threads_re.captures_iter(line) {
+                if let Ok(threads) = caps[1].parse::<u32>() {
+                    ret.threads = ret.threads.max(threads);
+                }
+            }
+            let rest = match find_get_after_last(line.trim(), "[RUSTC-TIMING] ") {
+                Some(rest) => rest,
+                None => continue,
+            };
+            let (timing, rusage) = match rest.find(" user: ") {
+                Some(pos) => (&rest[..pos], &rest[pos + 1..]),
+                None => continue,
+            };
+            let wall = timing.rsplit(' ').next().unwrap().parse::<f64>();
+            let user = rusage_value(rusage, "user: ");
+            let sys = rusage_value(rusage, "sys: ");
+            if let (Ok(wall), Some(user), Some(sys)) = (wall, user, sys) {
+                ret.wall += wall;
+                ret.cpu += user + sys;
+            }
+            if let Some(switches) = rusage_value(rusage, "voluntary ctxt switches: ") {
+                ret.voluntary_switches += switches as u64;
+            }
+        }
+        // Serial builds have nothing to say about parallelism
+        if ret.threads > 1 {
+            Some(ret)
+        } else {
+            None
+        }
+    }
+
+    /// Sums up the duration of each unit in any `cargo build --timings` HTML
+    /// report dumped into the log, which embeds its data as
+    /// `const UNIT_DATA = [...];`.
+    fn extract_cargo_timings(&self, contents: &str) -> BTreeMap<String, f64> {
+        #[derive(serde::Deserialize)]
+        struct Unit {
+            name: String,
+            version: String,
+            target: String,
+            duration: f64,
+        }
+
+        let needle = "const UNIT_DATA = ";
+        let mut ret = BTreeMap::new();
+        let mut lines = contents.lines().map(strip_timestamp);
+        while let Some(line) = lines.by_ref().find(|l| l.contains(needle)) {
+            // The array is pretty-printed over many lines, ending in `];`
+            let mut json = find_get_after(line, needle).unwrap().to_string();
+            while !json.trim_end().ends_with(';') {
+                match lines.next() {
+                    Some(line) => {
+                        json.push('\n');
+                        json.push_str(line);
+                    }
+                    None => break,
+                }
+            }
+            let json = json.trim_end().trim_end_matches(';');
+            let units = match serde_json::from_str::<Vec<Unit>>(json) {
+                Ok(units) => units,
+                Err(e) => {
+                    log::warn!("failed to parse cargo timings: {}", e);
+                    continue;
+                }
+            };
+            for unit in units {
+                let dur = match validate_duration(unit.duration, &unit.name) {
+                    Some(dur) => dur,
+                    None => continue,
+                };
+                let key = format!("{} v{}{}", unit.name, unit.version, unit.target);
+                *ret.entry(key).or_insert(0.0) += dur;
+            }
+        }
+        ret
+    }
+
+    /// Counts rustc's `warning:`/`error:` diagnostics, ignoring the summary
+    /// lines cargo and rustc print about them.
+    fn count_diagnostics(&self, contents: &str) -> (u32, u32) {
+        let is_diagnostic = |rest: &str| match rest.strip_prefix(':') {
+            Some(msg) => {
+                let msg = msg.trim();
+                !(msg.contains(" generated ")
+                    || msg.starts_with("aborting due to")
+                    || msg.starts_with("could not compile")
+                    || msg.starts_with("build failed"))
+            }
+            None => rest.starts_with('['),
+        };
+        let mut warnings = 0;
+        let mut errors = 0;
+        for line in contents.lines() {
+            let line = strip_timestamp(line.trim());
+            if line.strip_prefix("warning").is_some_and(is_diagnostic) {
+                warnings += 1;
+            } else if line.strip_prefix("error").is_some_and(is_diagnostic) {
+                errors += 1;
+            }
+        }
+        (warnings, errors)
+    }
+
+    fn parser_enabled(&self, name: &str) -> bool {
+        !self.skip_parsers.contains(name)
     }
 
     fn extract_cpu_microarch(&self, contents: &str) -> Option<String> {
         let mut family = None;
         for line in contents.lines() {
-            let line = line.trim();
+            // `/proc/cpuinfo` fields start their line, anchoring on that avoids
+            // matching field names mentioned elsewhere, like `model name`.
+            let line = strip_timestamp(line.trim());
             match family {
                 None => {
-                    if let Some(family_content) = find_get_after(line, "cpu family\t: ") {
-                        family = Some(family_content);
+                    if let Some(family_content) = line.strip_prefix("cpu family\t: ") {
+                        family = Some(family_content.trim());
                     }
                 }
                 Some(family) => {
-                    if let Some(model) = find_get_after(line, "model\t\t: ") {
+                    if let Some(model) = line.strip_prefix("model\t\t: ") {
+                        let model = model.trim();
                         return INTEL_CPU_MODEL_TO_MICROARCH
                             .iter()
                             .find(|(f, m, _)| *f == family && *m == model)
@@ -171,7 +1471,7 @@ impl Context {
     }
 
     fn identify_job(&self, log: &Log) -> Result<String, Error> {
-        let needle = "[CI_JOB_NAME=";
+        let needle = self.job_name_needle.as_str();
         let line = log
             .contents
             .lines()
@@ -183,7 +1483,7 @@ impl Context {
 
         // azure at one point buggily named everything `JobXX`
         if !contents.starts_with("Job") {
-            return Ok(contents.to_string())
+            return Ok(contents.to_string());
         }
 
         let needle = "AGENT_JOBNAME=";
@@ -194,24 +1494,84 @@ impl Context {
             .ok_or(format_err!("failed to find `{}`", needle))?;
         let pos = line.find(needle).unwrap();
         let contents = &line[pos + needle.len()..];
-        Ok(contents.split_whitespace().skip(1).next().unwrap().to_string())
+        match contents.split_whitespace().nth(1) {
+            Some(name) => Ok(name.to_string()),
+            None => bail!("no job name after `{}`", needle),
+        }
     }
 
-    fn logs(&mut self, commit: &str) -> Result<Vec<Log>, Error> {
-        while self.azure.get(commit).is_none() {
-            self.load_more_azure()?;
+    /// Fetches all logs for `commit`, returning `None` if its build hasn't
+    /// finished yet.
+    fn logs(&mut self, commit: &str) -> Result<Option<Vec<Log>>, Error> {
+        match self.ci {
+            Ci::Azure => {}
+            Ci::Github => return self.github_logs(commit),
+            Ci::Gitlab => return self.gitlab_logs(commit),
+        }
+        while !self.azure.contains_key(commit) {
+            if self.unfinished.contains(commit) {
+                return Ok(None);
+            }
+            // Try builds and reverts may never have been built on `auto`
+            if !self.load_more_azure()? {
+                log::warn!("no build of {} found on Azure", commit);
+                return Ok(None);
+            }
         }
 
         let mut logs = Vec::new();
         self.azure_logs(commit, &mut logs)?;
 
-        Ok(logs)
+        Ok(Some(logs))
     }
 
     fn azure_logs(&mut self, commit: &str, logs: &mut Vec<Log>) -> Result<(), Error> {
-        let build = &self.azure[commit];
-        let response = self.curl_azure().get_json::<azure::Timeline>(&build._links.timeline.href)?;
+        for run in 0..self.azure[commit].len() {
+            self.azure_build_logs(commit, run, logs)?;
+        }
+        Ok(())
+    }
+
+    fn azure_build_logs(
+        &mut self,
+        commit: &str,
+        run: usize,
+        logs: &mut Vec<Log>,
+    ) -> Result<(), Error> {
+        let href = &self.azure[commit][run]._links.timeline.href;
+        let response = match retry(|| self.azure_timeline(href)) {
+            Ok(response) => response,
+            Err(e) if run > 0 => {
+                println!("failed to fetch timeline for rerun {} of {}", run, commit);
+                println!("error: {}", e);
+                return Ok(());
+            }
+            // If we've seen this commit before its logs may all be cached,
+            // which is good enough to make progress with. The cache doesn't
+            // record which build a log is from though, so with re-runs there's
+            // no telling the primary build's logs apart.
+            Err(e) if self.azure[commit].len() > 1 => return Err(e),
+            Err(e) => {
+                let cached = self.cached_azure_logs(commit)?;
+                if cached.is_empty() {
+                    return Err(e);
+                }
+                println!(
+                    "failed to fetch timeline for {}, using {} cached logs",
+                    commit,
+                    cached.len()
+                );
+                println!("error: {}", e);
+                logs.extend(cached);
+                return Ok(());
+            }
+        };
 
+        let by_id = response
+            .records
+            .iter()
+            .map(|record| (record.id.as_str(), record))
+            .collect::<HashMap<_, _>>();
         let jobs = response
             .records
             .par_iter()
@@ -229,7 +1589,16 @@ impl Context {
 
                 true
             })
-            .map(|record| self.get_azure_log(commit, record).map_err(|e| (e, record)))
+            .map(|record| {
+                let mut parents = Vec::new();
+                let mut parent = record.parent_id.as_deref();
+                while let Some(p) = parent.and_then(|id| by_id.get(id)) {
+                    parents.extend(p.name.clone());
+                    parent = p.parent_id.as_deref();
+                }
+                self.get_azure_log(commit, run, record, parents)
+                    .map_err(|e| (e, record))
+            })
             .collect::<Vec<_>>();
         for job in jobs {
             match job {
@@ -239,6 +1608,7 @@ impl Context {
                 // fetch them. We're somewhat opportunistic anyway so just
                 // ignore it for now I guess?
                 Err((e, record)) => {
+                    self.stats.logs_failed.fetch_add(1, Ordering::SeqCst);
                     println!("failed to fetch {}/{}", commit, record.id);
                     println!("error: {}", e);
                 }
@@ -247,72 +1617,502 @@ impl Context {
         Ok(())
     }
 
-    fn get_azure_log(&self, commit: &str, record: &azure::TimelineRecord) -> Result<Log, Error> {
+    /// Fetches the whole timeline at `href`, following continuation tokens
+    /// for builds too large to fit in a single response.
+    fn azure_timeline(&self, href: &str) -> Result<azure::Timeline, Error> {
+        let mut timeline = azure::Timeline {
+            records: Vec::new(),
+        };
+        let mut url = href.to_string();
+        loop {
+            let (page, token) = self.curl_azure().get_json_paged::<azure::Timeline>(&url)?;
+            log::debug!("{} timeline records from {}", page.records.len(), url);
+            timeline.records.extend(page.records);
+            let token = match token {
+                Some(token) => token,
+                None => break,
+            };
+            let sep = if href.contains('?') { '&' } else { '?' };
+            let next = format!(
+                "{}{}continuationToken={}",
+                href,
+                sep,
+                shared::percent_encode(&token)
+            );
+            if next == url {
+                bail!("timeline at `{}` repeated its continuation token", href);
+            }
+            url = next;
+        }
+        let jobs = timeline
+            .records
+            .iter()
+            .filter(|r| r.r#type == "Job")
+            .count();
+        log::debug!(
+            "{} records, {} jobs, in {}",
+            timeline.records.len(),
+            jobs,
+            href
+        );
+        // Every record but the root hangs off another one, so any whose
+        // parent is missing means part of the timeline is too.
+        let ids = timeline
+            .records
+            .iter()
+            .map(|r| r.id.as_str())
+            .collect::<HashSet<_>>();
+        let orphans = timeline
+            .records
+            .iter()
+            .filter(|r| r.parent_id.as_deref().is_some_and(|p| !ids.contains(p)))
+            .count();
+        if orphans > 0 {
+            log::warn!(
+                "timeline at `{}` looks incomplete, {} of its {} records have \
+                 a missing parent",
+                href,
+                orphans,
+                timeline.records.len()
+            );
+        }
+        Ok(timeline)
+    }
+
+    /// Loads every log for `commit` already in the cache, without needing its
+    /// timeline, all as logs of its first build.
+    fn cached_azure_logs(&self, commit: &str) -> Result<Vec<Log>, Error> {
+        let dir = self.cache.join("logs/azure");
+        let mut logs = Vec::new();
+        if !dir.exists() {
+            return Ok(logs);
+        }
+        let prefix = format!("{}-", commit);
+        for entry in fs::read_dir(&dir)? {
+            let name = entry?.file_name().to_string_lossy().into_owned();
+            if !name.starts_with(&prefix) || !name.ends_with(".gz") {
+                continue;
+            }
+            let path = format!("logs/azure/{}", name);
+            let contents = self.get_log(&self.cache.join(&path), || {
+                bail!("log `{}` disappeared from the cache", path)
+            })?;
+            logs.push(Log {
+                job_url: String::new(),
+                contents,
+                path,
+                result: None,
+                run: 0,
+                parents: Vec::new(),
+                order: None,
+            });
+        }
+        Ok(logs)
+    }
+
+    fn get_azure_log(
+        &self,
+        commit: &str,
+        run: usize,
+        record: &azure::TimelineRecord,
+        parents: Vec<String>,
+    ) -> Result<Log, Error> {
         let log = record.log.as_ref().unwrap();
         let path = format!("logs/azure/{}-{}.gz", commit, record.id);
         let dst = self.cache.join(&path);
+        let contents = self.get_log(&dst, || self.curl_azure().get(&log.url))?;
+        Ok(Log {
+            job_url: log.url.clone(),
+            contents,
+            path,
+            result: record.result.clone(),
+            run,
+            parents,
+            order: record.order,
+        })
+    }
+
+    /// Fetches the job traces of every finished pipeline of `commit`, or
+    /// `None` if none of its pipelines have finished yet.
+    fn gitlab_logs(&mut self, commit: &str) -> Result<Option<Vec<Log>>, Error> {
+        if !self.gitlab.contains_key(commit) {
+            let path = format!("{}/pipelines?sha={}", self.gitlab_project_path(), commit);
+            let mut pipelines = Vec::new();
+            // Pipelines are listed newest first
+            for pipeline in retry(|| self.curl_gitlab().get_json::<Vec<gitlab::Pipeline>>(&path))?
+                .into_iter()
+                .rev()
+            {
+                if !pipeline.is_finished() {
+                    log::debug!(
+                        "ignoring pipeline {} of {} with status {}",
+                        pipeline.id,
+                        commit,
+                        pipeline.status
+                    );
+                    continue;
+                }
+                // Only the details include timing information
+                let path = format!("{}/pipelines/{}", self.gitlab_project_path(), pipeline.id);
+                pipelines.push(retry(|| self.curl_gitlab().get_json(&path))?);
+            }
+            if pipelines.is_empty() {
+                return Ok(None);
+            }
+            self.gitlab.insert(commit.to_string(), pipelines);
+        }
+
+        let mut logs = Vec::new();
+        for (run, pipeline) in self.gitlab[commit].iter().enumerate() {
+            let mut jobs = Vec::new();
+            for page in 1.. {
+                let path = format!(
+                    "{}/pipelines/{}/jobs?per_page=100&page={}",
+                    self.gitlab_project_path(),
+                    pipeline.id,
+                    page
+                );
+                let list = retry(|| self.curl_gitlab().get_json::<Vec<gitlab::Job>>(&path))?;
+                let done = list.len() < 100;
+                jobs.extend(list);
+                if done {
+                    break;
+                }
+            }
+            let fetched = jobs
+                .par_iter()
+                .map(|job| self.get_gitlab_log(commit, run, job).map_err(|e| (e, job)))
+                .collect::<Vec<_>>();
+            for log in fetched {
+                match log {
+                    Ok(log) => logs.push(log),
+                    Err((e, job)) => {
+                        self.stats.logs_failed.fetch_add(1, Ordering::SeqCst);
+                        println!("failed to fetch {}/{}", commit, job.id);
+                        println!("error: {}", e);
+                    }
+                }
+            }
+        }
+        Ok(Some(logs))
+    }
+
+    fn get_gitlab_log(&self, commit: &str, run: usize, job: &gitlab::Job) -> Result<Log, Error> {
+        let path = format!("logs/gitlab/{}-{}.gz", commit, job.id);
+        let dst = self.cache.join(&path);
         let contents = self.get_log(&dst, || {
-            self.curl_azure().get(&log.url)
+            self.curl_gitlab().get(&format!(
+                "{}/jobs/{}/trace",
+                self.gitlab_project_path(),
+                job.id
+            ))
         })?;
         Ok(Log {
-            job_url: log.url.clone(),
+            job_url: job.web_url.clone(),
+            contents,
+            path,
+            result: Some(job.result().to_string()),
+            run,
+            parents: vec![job.stage.clone()],
+            order: None,
+        })
+    }
+
+    /// Fetches the logs of every completed GitHub Actions run of `commit`, or
+    /// `None` if none of its runs have completed yet.
+    fn github_logs(&mut self, commit: &str) -> Result<Option<Vec<Log>>, Error> {
+        if !self.github.contains_key(commit) {
+            let path = format!(
+                "{}/actions/runs?head_sha={}",
+                self.github_repo_path(),
+                commit
+            );
+            let mut runs = Vec::new();
+            // Runs are listed newest first
+            for run in retry(|| self.curl_github().get_json::<github::Runs>(&path))?
+                .workflow_runs
+                .into_iter()
+                .rev()
+            {
+                if !run.is_finished() {
+                    log::debug!(
+                        "ignoring run {} of {} with status {} and conclusion {:?}",
+                        run.id,
+                        commit,
+                        run.status,
+                        run.conclusion
+                    );
+                    continue;
+                }
+                runs.push(run);
+            }
+            if runs.is_empty() {
+                return Ok(None);
+            }
+            self.github.insert(commit.to_string(), runs);
+        }
+
+        let mut logs = Vec::new();
+        for (run, gh_run) in self.github[commit].iter().enumerate() {
+            let mut jobs = Vec::new();
+            for page in 1.. {
+                let path = format!(
+                    "{}/actions/runs/{}/jobs?per_page=100&page={}",
+                    self.github_repo_path(),
+                    gh_run.id,
+                    page
+                );
+                let list = retry(|| self.curl_github().get_json::<github::Jobs>(&path))?.jobs;
+                let done = list.len() < 100;
+                jobs.extend(list);
+                if done {
+                    break;
+                }
+            }
+            let fetched = jobs
+                .par_iter()
+                .map(|job| self.get_github_log(commit, run, job).map_err(|e| (e, job)))
+                .collect::<Vec<_>>();
+            for log in fetched {
+                match log {
+                    Ok(log) => logs.push(log),
+                    Err((e, job)) => {
+                        self.stats.logs_failed.fetch_add(1, Ordering::SeqCst);
+                        println!("failed to fetch {}/{}", commit, job.id);
+                        println!("error: {}", e);
+                    }
+                }
+            }
+        }
+        Ok(Some(logs))
+    }
+
+    fn get_github_log(&self, commit: &str, run: usize, job: &github::Job) -> Result<Log, Error> {
+        let path = format!("logs/github/{}-{}.gz", commit, job.id);
+        let dst = self.cache.join(&path);
+        let contents = self.get_log(&dst, || {
+            // This redirects to wherever the log is actually stored
+            self.curl_github().follow_redirects().get(&format!(
+                "{}/actions/jobs/{}/logs",
+                self.github_repo_path(),
+                job.id
+            ))
+        })?;
+        Ok(Log {
+            job_url: job.html_url.clone(),
             contents,
             path,
+            result: Some(job.result().to_string()),
+            run,
+            parents: Vec::new(),
+            order: None,
         })
     }
 
+    /// Reads the log cached at `cache`, or fetches it with `get` and caches
+    /// it.
+    ///
+    /// Suspiciously short logs are usually an empty response from the CI
+    /// service rather than the real log, so those are retried and never
+    /// cached.
     fn get_log(
         &self,
         cache: &Path,
-        get: impl FnOnce() -> Result<String, Error>,
+        get: impl Fn() -> Result<String, Error>,
     ) -> Result<String, Error> {
         if cache.exists() {
-            let raw = fs::read(cache)?;
-            let mut contents = String::new();
-            flate2::read::GzDecoder::new(&raw[..]).read_to_string(&mut contents)?;
-            Ok(contents)
+            self.stats.logs_cache_hit.fetch_add(1, Ordering::SeqCst);
+            let mut contents = Vec::new();
+            flate2::read::GzDecoder::new(fs::File::open(cache)?).read_to_end(&mut contents)?;
+            Ok(lossy_string(contents))
         } else {
-            let log = get()?;
-            fs::create_dir_all(cache.parent().unwrap())?;
-            let mut raw = Vec::new();
-            let mut gz = flate2::write::GzEncoder::new(&mut raw, flate2::Compression::best());
-            gz.write_all(log.as_bytes())?;
-            gz.finish()?;
-            fs::create_dir_all(cache.parent().unwrap())?;
-            fs::write(cache, raw)?;
+            let log = retry(|| {
+                let log = get()?;
+                if log.len() < self.min_log_bytes {
+                    bail!("log is only {} bytes long", log.len());
+                }
+                Ok(log)
+            })?;
+            self.stats.logs_fetched.fetch_add(1, Ordering::SeqCst);
+            self.cache_write(cache, || {
+                fs::create_dir_all(cache.parent().unwrap())?;
+                self.write_compressed(cache, |w| Ok(w.write_all(log.as_bytes())?))
+            })?;
             Ok(log)
         }
     }
 
-    fn load_more_azure(&mut self) -> Result<(), Error> {
-        if self.azure.len() > 0 {
-            bail!("never did figure out the continuationToken thing");
+    /// Runs `write`, which writes `path` in the cache, only warning about
+    /// failures with `--cache-readonly`.
+    fn cache_write(
+        &self,
+        path: &Path,
+        write: impl FnOnce() -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        match write() {
+            Err(e) if self.cache_readonly => {
+                log::warn!("failed to write {:?} to the cache: {}", path, e);
+                Ok(())
+            }
+            result => result,
+        }
+    }
+
+    /// Gzips whatever `write` writes straight into `dst`, recording the sizes
+    /// involved.
+    fn write_compressed(
+        &self,
+        dst: &Path,
+        write: impl FnOnce(&mut dyn Write) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let compressed = compress(dst, write)?;
+        self.stats.record_compressed(&compressed);
+        Ok(())
+    }
+
+    /// Lists the next page of Azure's builds, returning `false` if there are
+    /// no more.
+    fn load_more_azure(&mut self) -> Result<bool, Error> {
+        if self.azure_exhausted {
+            return Ok(false);
         }
-        let mut path = format!("/rust-lang/rust/_apis/build/builds");
+        let mut path = "/rust-lang/rust/_apis/build/builds".to_string();
         path.push_str("?api-version=5.0");
         path.push_str("&branchName=refs/heads/auto");
         path.push_str("&queryOrder=finishTimeDescending");
-        let response = self.curl_azure().get_json::<azure::List>(&path)?;
+        if let Some(token) = &self.azure_continuation {
+            path.push_str("&continuationToken=");
+            path.push_str(&shared::percent_encode(token));
+        }
+        let (response, token) = self.curl_azure().get_json_paged::<azure::List>(&path)?;
+        self.azure_exhausted = token.is_none();
+        self.azure_continuation = token;
 
         for build in response.value {
-            self.azure.insert(build.source_version.clone(), build);
+            if build.is_finished() {
+                // Builds are listed newest first, but the first build of a
+                // commit is its primary one with any others being re-runs.
+                self.azure
+                    .entry(build.source_version.clone())
+                    .or_default()
+                    .insert(0, build);
+            } else {
+                log::debug!(
+                    "ignoring build of {} with status {} and result {:?}",
+                    build.source_version,
+                    build.status,
+                    build.result,
+                );
+                self.unfinished.insert(build.source_version);
+            }
         }
-        Ok(())
+        Ok(true)
     }
 
     fn curl(&self, host: &str) -> Curl {
         let mut ret = Curl::new(host);
         ret.header("User-Agent", "rustc-ci-timing-tracker");
-        return ret;
+        ret.timeouts(self.connect_timeout, self.max_time);
+        ret
+    }
+
+    fn curl_azure(&self) -> Curl {
+        self.curl("https://dev.azure.com")
+    }
+
+    fn curl_gitlab(&self) -> Curl {
+        let mut ret = self.curl(&self.gitlab_url);
+        if let Ok(token) = std::env::var("GITLAB_TOKEN") {
+            ret.header("PRIVATE-TOKEN", &token);
+        }
+        ret
+    }
+
+    fn curl_github(&self) -> Curl {
+        let mut ret = self.curl("https://api.github.com");
+        ret.header("Accept", "application/vnd.github+json");
+        if let Ok(token) = std::env::var("GITHUB_TOKEN") {
+            ret.header("Authorization", &format!("Bearer {}", token));
+        }
+        ret
+    }
+
+    fn github_repo_path(&self) -> String {
+        format!("/repos/{}", self.github_repo)
+    }
+
+    fn gitlab_project_path(&self) -> String {
+        format!(
+            "/api/v4/projects/{}",
+            self.gitlab_project.as_deref().unwrap_or_default()
+        )
+    }
+
+    fn curl_s3(&self) -> Curl {
+        self.curl(&shared::s3_url(self.s3_endpoint.as_deref()))
     }
+}
 
-    fn curl_azure(&self) -> Curl {
-        self.curl("https://dev.azure.com")
+impl Stats {
+    fn record_compressed(&self, compressed: &Compressed) {
+        self.bytes_uncompressed
+            .fetch_add(compressed.uncompressed, Ordering::SeqCst);
+        self.bytes_compressed
+            .fetch_add(compressed.compressed, Ordering::SeqCst);
     }
 
-    fn curl_s3(&self) -> Curl {
-        let bucket = env::var("S3_BUCKET").expect("missing environment variable S3_BUCKET");
-        self.curl(&format!("https://{}.s3.amazonaws.com", bucket))
+    fn print(&self) {
+        let get = |n: &AtomicUsize| n.load(Ordering::SeqCst);
+        println!("summary:");
+        println!(
+            "  commits: {} processed, {} newly cached, {} already cached",
+            get(&self.commits_processed),
+            get(&self.commits_cached),
+            get(&self.commits_skipped),
+        );
+        println!(
+            "  commits: {} skipped without finished builds, {} with too many \
+             unparseable logs",
+            get(&self.commits_unfinished),
+            get(&self.commits_unparseable),
+        );
+        println!(
+            "  logs: {} fetched, {} from cache, {} failed",
+            get(&self.logs_fetched),
+            get(&self.logs_cache_hit),
+            get(&self.logs_failed),
+        );
+        if get(&self.logs_cache_hit) + get(&self.logs_fetched) > 0 {
+            println!(
+                "  log cache: {:.1}% hit rate",
+                hit_rate(get(&self.logs_cache_hit), get(&self.logs_fetched)),
+            );
+        }
+        println!(
+            "  jobs: {} identified, {} failed identification",
+            get(&self.jobs_identified),
+            get(&self.jobs_unidentified),
+        );
+        let uncompressed = self.bytes_uncompressed.load(Ordering::SeqCst);
+        let compressed = self.bytes_compressed.load(Ordering::SeqCst);
+        if compressed > 0 {
+            println!(
+                "  compression: {} bytes down to {} bytes (ratio {:.2})",
+                uncompressed,
+                compressed,
+                uncompressed as f64 / compressed as f64,
+            );
+        }
+        let compressing = Duration::from_nanos(self.compress_nanos.load(Ordering::SeqCst));
+        let waiting = Duration::from_nanos(self.compress_wait_nanos.load(Ordering::SeqCst));
+        if compressing > Duration::from_secs(0) {
+            println!(
+                "  compression: {:.2}s compressing commits in the background, \
+                 {:.2}s of it spent waiting",
+                compressing.as_secs_f64(),
+                waiting.as_secs_f64(),
+            );
+        }
     }
 }
 
@@ -325,6 +2125,8 @@ impl Curl {
     fn new(host: &str) -> Curl {
         let mut cmd = Command::new("curl");
         cmd.arg("-sSf");
+        // Azure may gzip responses, which curl only decodes when asked to
+        cmd.arg("--compressed");
         Curl {
             cmd,
             host: host.to_string(),
@@ -338,22 +2140,53 @@ impl Curl {
         self
     }
 
+    fn follow_redirects(&mut self) -> &mut Curl {
+        self.cmd.arg("-L");
+        self
+    }
+
+    fn timeouts(&mut self, connect: u64, max: u64) -> &mut Curl {
+        self.cmd.arg("--connect-timeout").arg(connect.to_string());
+        self.cmd.arg("--max-time").arg(max.to_string());
+        self
+    }
+
     fn header(&mut self, name: &str, value: &str) -> &mut Curl {
-        self.cmd.arg("-H").arg(&format!("{}: {}", name, value));
+        self.cmd.arg("-H").arg(format!("{}: {}", name, value));
         self
     }
 
     fn get_json<T: for<'a> serde::Deserialize<'a>>(&mut self, path: &str) -> Result<T, Error> {
         let json = self.get(path)?;
-        let json = if log::log_enabled!(log::Level::Trace) {
-            let pretty: serde_json::Value = serde_json::from_str(&json)?;
-            let json = serde_json::to_string_pretty(&pretty)?;
-            log::trace!("decode {}", json);
-            json
-        } else {
-            json
-        };
-        Ok(serde_json::from_str(&json)?)
+        decode_json(json)
+    }
+
+    /// Like `get_json`, but also returns Azure's continuation token if the
+    /// response says there's more to fetch.
+    fn get_json_paged<T: for<'a> serde::Deserialize<'a>>(
+        &mut self,
+        path: &str,
+    ) -> Result<(T, Option<String>), Error> {
+        self.cmd.arg("-D").arg("-");
+        let mut response = self.get(path)?;
+        let mut token = None;
+        // There's a block of headers for every response, including redirects
+        // and `100 Continue`s, before the body.
+        while response.starts_with("HTTP/") {
+            let end = match response.find("\r\n\r\n") {
+                Some(end) => end,
+                None => bail!("malformed response headers from `{}`", path),
+            };
+            for line in response[..end].lines() {
+                let mut parts = line.splitn(2, ':');
+                let name = parts.next().unwrap().trim();
+                if name.eq_ignore_ascii_case("x-ms-continuationtoken") {
+                    token = parts.next().map(|v| v.trim().to_string());
+                }
+            }
+            response = response[end + 4..].to_string();
+        }
+        Ok((decode_json(response)?, token))
     }
 
     fn get(&mut self, path: &str) -> Result<String, Error> {
@@ -365,19 +2198,422 @@ impl Curl {
         log::debug!("GET: {}", url);
         let output = self.cmd.arg(&url).stderr(Stdio::inherit()).output()?;
         if output.status.success() {
-            Ok(String::from_utf8(output.stdout)?)
+            Ok(lossy_string(output.stdout))
         } else {
             bail!("failed to fetch `{}`: {}", url, output.status)
         }
     }
 }
 
+/// Converts `bytes` to a string, replacing any invalid UTF-8 rather than
+/// failing since logs occasionally contain binary spew.
+fn lossy_string(bytes: Vec<u8>) -> String {
+    String::from_utf8(bytes).unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned())
+}
+
+fn decode_json<T: for<'a> serde::Deserialize<'a>>(json: String) -> Result<T, Error> {
+    let json = if log::log_enabled!(log::Level::Trace) {
+        let pretty: serde_json::Value = serde_json::from_str(&json)?;
+        let json = serde_json::to_string_pretty(&pretty)?;
+        log::trace!("decode {}", json);
+        json
+    } else {
+        json
+    };
+    Ok(serde_json::from_str(&json)?)
+}
+
+/// Percentage of log reads served from the cache.
+fn hit_rate(hits: usize, fetches: usize) -> f64 {
+    hits as f64 / (hits + fetches) as f64 * 100.0
+}
+
+/// Gzips whatever `write` writes straight into `dst`.
+///
+/// The data goes to a temporary file first so an interrupted write never
+/// leaves a truncated file looking like it's cached.
+fn compress(
+    dst: &Path,
+    write: impl FnOnce(&mut dyn Write) -> Result<(), Error>,
+) -> Result<Compressed, Error> {
+    let start = Instant::now();
+    let mut tmp = dst.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    let file = io::BufWriter::new(fs::File::create(&tmp)?);
+    let gz = flate2::write::GzEncoder::new(file, flate2::Compression::best());
+    let mut counter = CountingWriter {
+        inner: gz,
+        count: 0,
+    };
+    write(&mut counter)?;
+    counter.inner.finish()?.flush()?;
+    let compressed = fs::metadata(&tmp)?.len();
+    fs::rename(&tmp, dst)?;
+    log::debug!(
+        "compressed {:?} from {} to {} bytes",
+        dst,
+        counter.count,
+        compressed
+    );
+    Ok(Compressed {
+        uncompressed: counter.count,
+        compressed,
+        elapsed: start.elapsed(),
+    })
+}
+
+/// Counts of what `merge-cache` did with each entry.
+#[derive(Default, Debug, PartialEq)]
+struct Merged {
+    copied: usize,
+    existing: usize,
+    corrupt: usize,
+}
+
+/// Reads the skip list at `path`, one SHA per line with `#` comments.
+fn read_skip_list(path: &Path) -> Result<HashSet<String>, Error> {
+    if !path.exists() {
+        return Ok(HashSet::new());
+    }
+    Ok(fs::read_to_string(path)?
+        .lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(|l| l.to_string())
+        .collect())
+}
+
+/// Adds the entries of `new` which aren't already in the manifest `entries`,
+/// keeping it sorted newest first like `git log`.
+fn merge_manifest(entries: &mut Vec<ManifestEntry>, new: Vec<ManifestEntry>) {
+    let known = entries
+        .iter()
+        .map(|e| e.sha.clone())
+        .collect::<HashSet<_>>();
+    entries.extend(new.into_iter().filter(|e| !known.contains(&e.sha)));
+    entries.sort_by_cached_key(|e| std::cmp::Reverse(e.git_commit().date));
+}
+
+/// Copies every gzipped file under `src` which isn't yet in `dst` and
+/// decompresses cleanly.
+fn merge_dir(src: &Path, dst: &Path, merged: &mut Merged) -> Result<(), Error> {
+    if !src.exists() {
+        return Ok(());
+    }
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let path = entry.path();
+        let dst = dst.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            merge_dir(&path, &dst, merged)?;
+            continue;
+        }
+        if path.extension() != Some("gz".as_ref()) {
+            continue;
+        }
+        if dst.exists() {
+            merged.existing += 1;
+            continue;
+        }
+        let contents = fs::read(&path)?;
+        let mut gz = flate2::read::GzDecoder::new(&contents[..]);
+        if let Err(e) = io::copy(&mut gz, &mut io::sink()) {
+            log::warn!("skipping corrupt {:?}: {}", path, e);
+            merged.corrupt += 1;
+            continue;
+        }
+        // Like the cache's own writes, go through a temporary file so an
+        // interrupted merge doesn't leave a truncated entry behind.
+        let mut tmp = dst.as_os_str().to_owned();
+        tmp.push(".tmp");
+        fs::create_dir_all(dst.parent().unwrap())?;
+        fs::write(&tmp, &contents)?;
+        fs::rename(&tmp, &dst)?;
+        merged.copied += 1;
+    }
+    Ok(())
+}
+
+/// Passes writes through to `inner`, counting the bytes written.
+struct CountingWriter<W> {
+    inner: W,
+    count: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Runs `f` up to three times with a short backoff, for papering over flaky
+/// network requests.
+fn retry<T>(mut f: impl FnMut() -> Result<T, Error>) -> Result<T, Error> {
+    let mut attempt = 1;
+    loop {
+        match f() {
+            Ok(t) => return Ok(t),
+            Err(e) if attempt < 3 => {
+                log::debug!("attempt {} failed, retrying: {}", attempt, e);
+                thread::sleep(Duration::from_secs(attempt));
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Merges two attempts at the same job within a build, preferring the timings
+/// of the attempt which succeeded since a failed attempt's timings include
+/// wasted work.
+fn merge_retried(a: Job, b: Job) -> Job {
+    let retries = a.retries + b.retries + 1;
+    let succeeded = |job: &Job| job.result.as_deref() == Some("succeeded");
+    let mut job = if succeeded(&a) && !succeeded(&b) {
+        a
+    } else {
+        b
+    };
+    job.retries = retries;
+    job
+}
+
+/// The longest a single step can plausibly take, anything longer most likely
+/// means the log's units changed out from under us.
+const MAX_PLAUSIBLE_DURATION: f64 = 24.0 * 60.0 * 60.0;
+
+/// Name of the step holding crates compiled in a job without any steps.
+const UNATTRIBUTED_STEP: &str = "(unattributed)";
+
+/// Parses the size of a build directory from a line of `du -s` (or `du -sh`)
+/// output like `12345\t/checkout/obj` or `1.5G\ttarget`, in bytes.
+fn du_size(line: &str) -> Option<u64> {
+    let mut parts = line.splitn(2, '\t');
+    let size = parts.next()?;
+    let path = parts.next()?.trim().trim_end_matches('/');
+    let dir = path.rsplit('/').next()?;
+    if !["target", "obj", "build"].contains(&dir) {
+        return None;
+    }
+    // Plain `du` counts in kibibytes
+    let (num, unit) = match size.char_indices().last()? {
+        (i, c) if c.is_ascii_alphabetic() => (&size[..i], c),
+        _ => (size, 'K'),
+    };
+    let scale = match unit {
+        'K' => 1u64 << 10,
+        'M' => 1 << 20,
+        'G' => 1 << 30,
+        'T' => 1 << 40,
+        _ => return None,
+    };
+    let num = num.parse::<f64>().ok()?;
+    if !num.is_finite() || num < 0.0 {
+        return None;
+    }
+    Some((num * scale as f64) as u64)
+}
+
+/// Finds the number following `label` in bootstrap's resource usage output.
+fn rusage_value(rusage: &str, label: &str) -> Option<f64> {
+    // `voluntary` is also the end of `involuntary`, so labels have to start
+    // at the beginning of a word.
+    let pos = rusage
+        .match_indices(label)
+        .find(|(pos, _)| *pos == 0 || rusage.as_bytes()[pos - 1] == b' ')?
+        .0;
+    rusage[pos + label.len()..]
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()
+}
+
+/// Cargo subcommands which steps are tagged with.
+const CARGO_COMMANDS: &[&str] = &[
+    "bench", "build", "check", "clippy", "doc", "rustc", "rustdoc", "test",
+];
+
+/// Finds the subcommand of a cargo invocation echoed in `line`, like
+/// bootstrap's `running: "cargo" "build" ...` or a shell's `+ cargo doc`.
+fn cargo_command(line: &str) -> Option<&'static str> {
+    let words = line
+        .split_whitespace()
+        .map(|w| w.trim_matches('"'))
+        // Skips toolchain overrides like `+nightly`, along with the shell's
+        // `+` prefix
+        .filter(|w| !w.starts_with('+'))
+        .collect::<Vec<_>>();
+    words.windows(2).find_map(|pair| {
+        let program = pair[0].rsplit(['/', '\\']).next().unwrap();
+        if program != "cargo" && program != "cargo.exe" {
+            return None;
+        }
+        CARGO_COMMANDS.iter().find(|c| **c == pair[1]).copied()
+    })
+}
+
+/// Moves the crates accumulated by `extract_timings` into a step's parts,
+/// each one's total nanoseconds and how many times it was compiled.
+fn flush_parts(parts: &mut HashMap<String, (u64, u32)>, step: &mut HashMap<String, (u64, u32)>) {
+    for (k, (total, count)) in parts.drain() {
+        let (step_total, step_count) = step.entry(k).or_insert((0, 0));
+        *step_total += total;
+        *step_count += count;
+    }
+}
+
+/// Name of the part standing in for all those under `--min-part-secs`.
+const BELOW_THRESHOLD_PART: &str = "below_threshold";
+
+/// Moves the parts of a step which took less than `min` nanoseconds into one
+/// `BELOW_THRESHOLD_PART`, so the total is still accurate.
+fn lump_small_parts(parts: &mut HashMap<String, (u64, u32)>, min: u64) {
+    let small = parts
+        .iter()
+        .filter(|(name, (total, _))| *total < min && *name != BELOW_THRESHOLD_PART)
+        .map(|(name, _)| name.clone())
+        .collect::<Vec<_>>();
+    if small.is_empty() {
+        return;
+    }
+    let mut lumped = (0, 0);
+    for name in small {
+        let (total, count) = parts.remove(&name).unwrap();
+        lumped.0 += total;
+        lumped.1 += count;
+    }
+    let (total, count) = parts
+        .entry(BELOW_THRESHOLD_PART.to_string())
+        .or_insert((0, 0));
+    *total += lumped.0;
+    *count += lumped.1;
+}
+
+fn secs_to_nanos(secs: f64) -> u64 {
+    (secs * 1e9).round() as u64
+}
+
+fn nanos_to_secs(nanos: u64) -> f64 {
+    nanos as f64 / 1e9
+}
+
+/// Sanity checks a duration (in `shared::TIMING_UNIT`) parsed from `line`,
+/// warning about and dropping it if it's implausible.
+fn validate_duration(dur: f64, line: &str) -> Option<f64> {
+    if dur.is_finite() && (0.0..=MAX_PLAUSIBLE_DURATION).contains(&dur) {
+        Some(dur)
+    } else {
+        log::warn!("ignoring implausible duration {} in `{}`", dur, line);
+        None
+    }
+}
+
+/// Removes ANSI escape sequences, such as terminal colors, from `s`.
+fn strip_ansi(s: &str) -> Cow<'_, str> {
+    if !s.contains('\x1b') {
+        return Cow::Borrowed(s);
+    }
+    let mut ret = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            ret.push(c);
+            continue;
+        }
+        // Control sequences run until a final byte in `@`..=`~`, while any
+        // other escape is just the following character.
+        if chars.next_if_eq(&'[').is_some() {
+            for c in chars.by_ref() {
+                if ('@'..='~').contains(&c) {
+                    break;
+                }
+            }
+        } else {
+            chars.next();
+        }
+    }
+    Cow::Owned(ret)
+}
+
+/// Whether the Azure section `name` fetches the source rather than building.
+fn is_fetch_section(name: &str) -> bool {
+    let name = name.to_lowercase();
+    ["checkout", "submodule", "fetch", "clone"]
+        .iter()
+        .any(|word| name.contains(word))
+}
+
+/// Parses the timestamp Azure puts at the start of each log line.
+fn line_timestamp(line: &str) -> Option<chrono::DateTime<chrono::FixedOffset>> {
+    let first = line.split_whitespace().next()?;
+    chrono::DateTime::parse_from_rfc3339(first).ok()
+}
+
+/// Strips the timestamp Azure puts at the start of each log line, if any.
+fn strip_timestamp(line: &str) -> &str {
+    match line.find(' ') {
+        Some(pos) if line_timestamp(&line[..pos]).is_some() => line[pos + 1..].trim_start(),
+        _ => line,
+    }
+}
+
+/// Parses a human readable elapsed time like `12.34s` or `1h 2m 3s` into
+/// seconds.
+fn parse_elapsed(s: &str) -> Option<f64> {
+    let mut total = 0.0;
+    for part in s.split_whitespace() {
+        let (num, scale) = if let Some(n) = part.strip_suffix('h') {
+            (n, 3600.0)
+        } else if let Some(n) = part.strip_suffix('m') {
+            (n, 60.0)
+        } else if let Some(n) = part.strip_suffix('s') {
+            (n, 1.0)
+        } else {
+            return None;
+        };
+        total += num.parse::<f64>().ok()? * scale;
+    }
+    Some(total)
+}
+
+/// Parses a single duration like `1234ms`, `1.5s` or `2m` into seconds, with
+/// bare numbers taken to be seconds as rustc has always printed them.
+fn parse_duration(s: &str) -> Option<f64> {
+    let s = s.trim();
+    let (num, scale) = if let Some(n) = s.strip_suffix("ms") {
+        (n, 0.001)
+    } else if let Some(n) = s.strip_suffix('s') {
+        (n, 1.0)
+    } else if let Some(n) = s.strip_suffix('m') {
+        (n, 60.0)
+    } else {
+        (s, 1.0)
+    };
+    Some(num.parse::<f64>().ok()? * scale)
+}
+
+/// Returns everything in `content` after the first occurrence of `needle`.
 fn find_get_after<'a>(content: &'a str, needle: &str) -> Option<&'a str> {
     content
         .find(needle)
         .map(|pos| &content[pos + needle.len()..])
 }
 
+/// Like `find_get_after`, but after the last occurrence of `needle`.
+fn find_get_after_last<'a>(content: &'a str, needle: &str) -> Option<&'a str> {
+    content
+        .rfind(needle)
+        .map(|pos| &content[pos + needle.len()..])
+}
+
 /// Map the CPU family and model to the microarchitecture name
 /// Source for the data: https://en.wikichip.org/wiki/intel/cpuid
 static INTEL_CPU_MODEL_TO_MICROARCH: &[(&str, &str, &str)] = &[
@@ -400,9 +2636,37 @@ mod azure {
     pub struct Build {
         #[serde(rename = "sourceVersion")]
         pub source_version: String,
+        #[serde(rename = "buildNumber")]
+        pub build_number: String,
+        #[serde(rename = "queueTime")]
+        pub queue_time: Option<String>,
+        #[serde(rename = "startTime")]
+        pub start_time: Option<String>,
+        #[serde(rename = "finishTime")]
+        pub finish_time: Option<String>,
+        pub status: String,
+        pub result: Option<String>,
         pub _links: BuildLinks,
     }
 
+    impl Build {
+        /// Whether this build ran to completion, as opposed to still running
+        /// or having been canceled part way through.
+        pub fn is_finished(&self) -> bool {
+            self.status == "completed"
+                && matches!(self.result.as_deref(), Some("succeeded") | Some("failed"))
+        }
+
+        /// Wall time in seconds from the build starting to finishing.
+        pub fn duration(&self) -> Option<f64> {
+            let parse =
+                |time: &Option<String>| chrono::DateTime::parse_from_rfc3339(time.as_ref()?).ok();
+            let start = parse(&self.start_time)?;
+            let finish = parse(&self.finish_time)?;
+            Some((finish - start).num_milliseconds() as f64 / 1000.0)
+        }
+    }
+
     #[derive(serde::Deserialize)]
     pub struct BuildLinks {
         pub timeline: Link,
@@ -421,7 +2685,12 @@ mod azure {
     #[derive(serde::Deserialize)]
     pub struct TimelineRecord {
         pub id: String,
+        #[serde(rename = "parentId")]
+        pub parent_id: Option<String>,
+        pub name: Option<String>,
+        pub order: Option<u32>,
         pub r#type: String,
+        pub result: Option<String>,
         pub log: Option<TimelineLog>,
     }
 
@@ -430,3 +2699,604 @@ mod azure {
         pub url: String,
     }
 }
+
+#[allow(dead_code)]
+mod gitlab {
+    #[derive(serde::Deserialize)]
+    pub struct Pipeline {
+        pub id: u64,
+        pub iid: u64,
+        pub sha: String,
+        pub status: String,
+        pub created_at: String,
+        #[serde(default)]
+        pub started_at: Option<String>,
+        #[serde(default)]
+        pub finished_at: Option<String>,
+        /// Only present in a single pipeline's details, in seconds
+        #[serde(default)]
+        pub duration: Option<f64>,
+    }
+
+    impl Pipeline {
+        /// Whether this pipeline ran to completion, as opposed to still
+        /// running or having been canceled part way through.
+        pub fn is_finished(&self) -> bool {
+            self.status == "success" || self.status == "failed"
+        }
+    }
+
+    #[derive(serde::Deserialize)]
+    pub struct Job {
+        pub id: u64,
+        pub name: String,
+        pub stage: String,
+        pub status: String,
+        pub web_url: String,
+    }
+
+    impl Job {
+        /// The job's status in the same terms as Azure's results, so the
+        /// rest of the pipeline can treat them alike.
+        pub fn result(&self) -> &str {
+            match self.status.as_str() {
+                "success" => "succeeded",
+                other => other,
+            }
+        }
+    }
+}
+
+#[allow(dead_code)]
+mod github {
+    #[derive(serde::Deserialize)]
+    pub struct Runs {
+        pub workflow_runs: Vec<Run>,
+    }
+
+    #[derive(serde::Deserialize)]
+    pub struct Run {
+        pub id: u64,
+        pub run_number: u64,
+        pub head_sha: String,
+        pub status: String,
+        #[serde(default)]
+        pub conclusion: Option<String>,
+        pub created_at: String,
+        #[serde(default)]
+        pub run_started_at: Option<String>,
+        pub updated_at: String,
+    }
+
+    impl Run {
+        /// Whether this run ran to completion, as opposed to still running or
+        /// having been cancelled or skipped.
+        pub fn is_finished(&self) -> bool {
+            self.status == "completed"
+                && matches!(self.conclusion.as_deref(), Some("success" | "failure"))
+        }
+
+        /// Wall time in seconds from the run starting to it last being
+        /// updated, which for a completed run is when it finished.
+        pub fn duration(&self) -> Option<f64> {
+            let start = chrono::DateTime::parse_from_rfc3339(self.run_started_at.as_ref()?).ok()?;
+            let finish = chrono::DateTime::parse_from_rfc3339(&self.updated_at).ok()?;
+            Some((finish - start).num_milliseconds() as f64 / 1000.0)
+        }
+    }
+
+    #[derive(serde::Deserialize)]
+    pub struct Jobs {
+        pub jobs: Vec<Job>,
+    }
+
+    #[derive(serde::Deserialize)]
+    pub struct Job {
+        pub id: u64,
+        pub name: String,
+        pub status: String,
+        #[serde(default)]
+        pub conclusion: Option<String>,
+        pub html_url: String,
+    }
+
+    impl Job {
+        /// The job's conclusion in the same terms as Azure's results, so the
+        /// rest of the pipeline can treat them alike.
+        pub fn result(&self) -> &str {
+            match self.conclusion.as_deref() {
+                Some("success") => "succeeded",
+                Some("failure") => "failed",
+                Some(other) => other,
+                None => &self.status,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cx() -> Context {
+        cx_with(&[])
+    }
+
+    fn cx_with(flags: &[&str]) -> Context {
+        let mut argv = vec!["publish-data-to-s3"];
+        argv.extend(flags);
+        argv.extend(["rust", "cache"]);
+        let args: Args = docopt::Docopt::new(USAGE)
+            .and_then(|d| d.argv(argv).deserialize())
+            .unwrap();
+        Context::new(&args).unwrap()
+    }
+
+    #[test]
+    fn trailing_parts_go_to_the_last_step() {
+        let log = "\
+[RUSTC-TIMING] core test:false 10.0
+[TIMING] build std -- 12.0
+[RUSTC-TIMING] syn test:false 1.5
+[TIMING] build rustc -- 30.0
+[RUSTC-TIMING] serde test:false 2.0
+[RUSTC-TIMING] serde test:false 0.5
+";
+        let timings = cx().extract_timings(log);
+        assert_eq!(timings.len(), 2);
+        let std = &timings["build std"];
+        assert_eq!(std.parts.keys().collect::<Vec<_>>(), ["core test:false"]);
+        let rustc = &timings["build rustc"];
+        assert_eq!(rustc.dur, 30.0);
+        assert_eq!(rustc.parts["syn test:false"], 1.5);
+        assert_eq!(rustc.parts["serde test:false"], 2.5);
+        assert_eq!(rustc.part_counts["serde test:false"], 2);
+    }
+
+    #[test]
+    fn find_after_repeated_needles() {
+        let line = "[echo] [RUSTC-TIMING] x [RUSTC-TIMING] syn test:false 1.5";
+        assert_eq!(
+            find_get_after(line, "[RUSTC-TIMING] "),
+            Some("x [RUSTC-TIMING] syn test:false 1.5")
+        );
+        assert_eq!(
+            find_get_after_last(line, "[RUSTC-TIMING] "),
+            Some("syn test:false 1.5")
+        );
+        assert_eq!(find_get_after_last(line, "[TIMING] "), None);
+    }
+
+    #[test]
+    fn rustc_timing_echoed_in_another_line() {
+        let log = "\
++ echo '[RUSTC-TIMING] ' [RUSTC-TIMING] syn test:false 1.5
+[TIMING] build -- 2.0
+";
+        let timings = cx().extract_timings(log);
+        assert_eq!(timings["build"].parts["syn test:false"], 1.5);
+    }
+
+    #[test]
+    fn test_suite_named_finished_in() {
+        let log = "\
+compiletest suite=ui mode=ui
+test result: ok. 1 passed; finished in 1s; finished in 12.5s
+";
+        assert_eq!(cx().extract_test_suites(log)["ui"], 12.5);
+    }
+
+    #[test]
+    fn cpu_model_ignores_other_fields() {
+        let log = "\
+cpu family\t: 6
+model name\t: Intel(R) Xeon(R) CPU E5-2673 v4 @ 2.30GHz
+echo model\t\t: 1
+model\t\t: 79
+";
+        assert_eq!(
+            cx().extract_cpu_microarch(log).as_deref(),
+            Some("broadwell")
+        );
+    }
+
+    #[test]
+    fn time_passes_after_azure_timestamps() {
+        let log = "\
+2020-01-01T00:00:00.0000000Z time:   1.500; rss:   50MB ->  100MB\ttypeck
+2020-01-01T00:00:01.0000000Z time:   0.250; user: 0.200; sys: 0.050\ttypeck
+2020-01-01T00:00:02.0000000Z runtime: 3.0\tnot_a_pass
+";
+        let passes = cx().extract_time_passes(log);
+        assert_eq!(passes.keys().collect::<Vec<_>>(), ["typeck"]);
+        assert_eq!(passes["typeck"].dur, 1.75);
+        assert_eq!(passes["typeck"].user, Some(0.2));
+    }
+
+    #[test]
+    fn normalized_steps_merge() {
+        let log = "\
+[TIMING] Build  std -- 1.0
+[TIMING]  Build std  -- 2.0
+[TIMING] build std -- 4.0
+";
+        let timings = cx().extract_timings(log);
+        assert_eq!(timings.len(), 3);
+        let timings = cx_with(&["--normalize-steps"]).extract_timings(log);
+        assert_eq!(timings.len(), 2);
+        assert_eq!(timings["Build std"].dur, 3.0);
+        let timings = cx_with(&["--lowercase-steps"]).extract_timings(log);
+        assert_eq!(timings.len(), 1);
+        assert_eq!(timings["build std"].dur, 7.0);
+    }
+
+    #[test]
+    fn parts_without_any_step_are_kept() {
+        let log = "\
+[RUSTC-TIMING] syn test:false 1.5
+[RUSTC-TIMING] serde test:false 2.0
+";
+        let timings = cx().extract_timings(log);
+        let timing = &timings[UNATTRIBUTED_STEP];
+        assert_eq!(timing.dur, 3.5);
+        assert_eq!(timing.parts.len(), 2);
+    }
+
+    #[test]
+    fn readonly_cache_write_failures_are_ignored() {
+        let fail = || Err(format_err!("read-only file system"));
+        assert!(cx().cache_write(Path::new("x"), fail).is_err());
+        let cx = cx_with(&["--cache-readonly"]);
+        assert!(cx.cache_write(Path::new("x"), fail).is_ok());
+    }
+
+    #[test]
+    fn steps_are_tagged_with_cargo_command() {
+        let log = "\
+running: \"/checkout/obj/build/x86_64-unknown-linux-gnu/stage0/bin/cargo\" \"check\" \"--target\"
+[RUSTC-TIMING] serde test:false 0.5
+[TIMING] check std -- 10.0
++ cargo +nightly doc --no-deps
+[RUSTC-TIMING] serde test:false 1.0
+[TIMING] doc std -- 20.0
+Compiling cargo v0.1.0
+[TIMING] ToolBuild { tool: \"cargo\" } -- 5.0
+";
+        let timings = cx().extract_timings(log);
+        assert_eq!(timings["check std"].cargo_command.as_deref(), Some("check"));
+        assert_eq!(timings["doc std"].cargo_command.as_deref(), Some("doc"));
+        assert_eq!(timings["ToolBuild { tool: \"cargo\" }"].cargo_command, None);
+    }
+
+    #[test]
+    fn parallel_rustc_rusage() {
+        let log = "\
+running: \"rustc\" \"-Zthreads=8\" \"--crate-name\" \"syn\"
+[RUSTC-TIMING] syn test:false 2.000 user: 5.000 sys: 1.000 max rss (kb): 1234 voluntary ctxt switches: 10 involuntary ctxt switches: 99
+[RUSTC-TIMING] serde test:false 1.000 user: 1.500 sys: 0.500 max rss (kb): 1234 voluntary ctxt switches: 5 involuntary ctxt switches: 1
+[TIMING] build std -- 10.0
+";
+        let cx = cx();
+        let parallel = cx.extract_parallelism(log).unwrap();
+        assert_eq!(parallel.threads, 8);
+        assert_eq!(parallel.wall, 3.0);
+        assert_eq!(parallel.cpu, 8.0);
+        assert_eq!(parallel.voluntary_switches, 15);
+        let timings = cx.extract_timings(log);
+        assert_eq!(timings["build std"].parts["syn test:false"], 2.0);
+
+        let serial = log.replace("-Zthreads=8", "-Zthreads=1");
+        assert!(cx.extract_parallelism(&serial).is_none());
+    }
+
+    #[test]
+    fn parts_flushed_only_into_the_target() {
+        let log = "\
+[RUSTC-TIMING] core test:false 10.0
+[TIMING] doc std -- 5.0
+[RUSTC-TIMING] syn test:false 1.5
+[TIMING] build std -- 12.0
+[RUSTC-TIMING] serde test:false 2.0
+[TIMING] doc rustc -- 3.0
+";
+        let timings = cx_with(&["--flush-target", "^build"]).extract_timings(log);
+        assert!(timings["doc std"].parts.is_empty());
+        assert!(timings["doc rustc"].parts.is_empty());
+        let build = &timings["build std"].parts;
+        assert_eq!(
+            build.keys().collect::<Vec<_>>(),
+            ["core test:false", "serde test:false", "syn test:false"]
+        );
+    }
+
+    #[test]
+    fn target_size_from_du() {
+        let log = "\
+[TIMING] build std -- 12.0
+1.5G\t/checkout/obj
+[TIMING] build rustc -- 30.0
+[TIMING] doc std -- 3.0
+2048\ttarget/
+[TIMING] dist -- 3.0
+";
+        let timings = cx().extract_timings(log);
+        assert_eq!(timings["build std"].target_size, None);
+        assert_eq!(timings["build rustc"].target_size, Some(3 << 29));
+        assert_eq!(timings["doc std"].target_size, None);
+        assert_eq!(timings["dist"].target_size, Some(2 << 20));
+        assert_eq!(du_size("12\tsrc/lib.rs"), None);
+    }
+
+    #[test]
+    fn small_parts_are_lumped_together() {
+        let log = "\
+[RUSTC-TIMING] syn test:false 1.5
+[RUSTC-TIMING] cfg_if test:false 0.001
+[RUSTC-TIMING] cfg_if test:false 0.002
+[RUSTC-TIMING] libc test:false 0.1
+[TIMING] build std -- 12.0
+";
+        let timings = cx_with(&["--min-part-secs", "0.5"]).extract_timings(log);
+        let timing = &timings["build std"];
+        assert_eq!(
+            timing.parts.keys().collect::<Vec<_>>(),
+            ["below_threshold", "syn test:false"]
+        );
+        assert_eq!(timing.parts["below_threshold"], 0.103);
+        assert_eq!(timing.part_counts["below_threshold"], 3);
+
+        let timings = cx().extract_timings(log);
+        assert_eq!(timings["build std"].parts.len(), 3);
+    }
+
+    #[test]
+    fn durations_with_units() {
+        assert_eq!(parse_duration("1.5"), Some(1.5));
+        assert_eq!(parse_duration("1234ms"), Some(1.234));
+        assert_eq!(parse_duration("1.5s"), Some(1.5));
+        assert_eq!(parse_duration("2m"), Some(120.0));
+        assert_eq!(parse_duration("2h"), None);
+        assert_eq!(parse_duration("ms"), None);
+    }
+
+    #[test]
+    fn mixed_units_in_one_log() {
+        let log = "\
+[RUSTC-TIMING] syn test:false 1500ms
+[RUSTC-TIMING] serde test:false 2s
+[TIMING] build std -- 1.5m
+[TIMING] build rustc -- 30
+";
+        let timings = cx().extract_timings(log);
+        assert_eq!(timings["build std"].dur, 90.0);
+        assert_eq!(timings["build std"].parts["syn test:false"], 1.5);
+        assert_eq!(timings["build std"].parts["serde test:false"], 2.0);
+        assert_eq!(timings["build rustc"].dur, 30.0);
+    }
+
+    #[test]
+    fn invalid_utf8_in_a_log() {
+        let log = b"[RUSTC-TIMING] syn test:false 1.5\n\xff\xfe garbage\n[TIMING] build -- 2.0\n";
+        let timings = cx().extract_timings(&lossy_string(log.to_vec()));
+        assert_eq!(timings["build"].dur, 2.0);
+        assert_eq!(timings["build"].parts["syn test:false"], 1.5);
+    }
+
+    #[test]
+    fn ansi_colored_markers() {
+        let log = "\
+\x1b[32m[RUSTC-TIMING]\x1b[0m syn test:false 1.5
+\x1b[1;32m[TIMING]\x1b[0m build -- \x1b[33m2.0\x1b[0m
+";
+        let stripped = strip_ansi(log);
+        assert_eq!(
+            stripped,
+            "[RUSTC-TIMING] syn test:false 1.5\n[TIMING] build -- 2.0\n"
+        );
+        let timings = cx().extract_timings(&stripped);
+        assert_eq!(timings["build"].dur, 2.0);
+        assert_eq!(timings["build"].parts["syn test:false"], 1.5);
+    }
+
+    #[test]
+    fn parts_of_repeated_steps_sum_exactly() {
+        let log = "\
+[RUSTC-TIMING] syn test:false 0.1
+[TIMING] build -- 1.0
+[RUSTC-TIMING] syn test:false 0.2
+[RUSTC-TIMING] libc test:false 0.01
+[TIMING] build -- 1.0
+";
+        // Summed as floats this would be 0.30000000000000004
+        let timings = cx().extract_timings(log);
+        assert_eq!(timings["build"].parts["syn test:false"], 0.3);
+        assert_eq!(timings["build"].part_counts["syn test:false"], 2);
+        let timings = cx_with(&["--min-part-secs", "0.05"]).extract_timings(log);
+        assert_eq!(timings["build"].parts["below_threshold"], 0.01);
+        assert_eq!(timings["build"].parts.len(), 2);
+    }
+
+    #[test]
+    fn parallel_docker_build_steps() {
+        let log = "\
+#5 DONE 10.0s
+#6 DONE 12.0s
+#7 DONE 3.0s
+";
+        assert_eq!(cx().extract_docker_build(log), Some(25.0));
+        // Steps 5 and 6 ran side by side, 7 only once 6 was done
+        let log = "\
+2020-01-01T00:00:10.0000000Z #5 DONE 10.0s
+2020-01-01T00:00:12.0000000Z #6 DONE 12.0s
+2020-01-01T00:00:15.0000000Z #7 DONE 3.0s
+";
+        assert_eq!(cx().extract_docker_build(log), Some(15.0));
+    }
+
+    #[test]
+    fn fetch_sections() {
+        let log = "\
+2020-01-01T00:00:00.0000000Z ##[section]Starting: Checkout
+2020-01-01T00:00:30.5000000Z ##[section]Finishing: Checkout
+2020-01-01T00:00:31.0000000Z ##[section]Starting: Run build
+2020-01-01T00:01:00.0000000Z ##[section]Starting: Checkout submodules
+2020-01-01T00:03:00.0000000Z ##[section]Finishing: Checkout submodules
+2020-01-01T01:00:00.0000000Z ##[section]Finishing: Run build
+";
+        assert_eq!(cx().extract_fetch(log), Some(150.5));
+        assert_eq!(cx().extract_fetch("[TIMING] build -- 1.0"), None);
+    }
+
+    /// Feeds random mixes of bytes and fragments of the markers parsers look
+    /// for through every parser, which must never panic on a corrupt log.
+    #[test]
+    fn parsers_never_panic() {
+        const FRAGMENTS: &[&str] = &[
+            "[TIMING] ",
+            "[RUSTC-TIMING] ",
+            " -- ",
+            " test:false ",
+            " user: ",
+            "1.5",
+            "-3",
+            "1e400",
+            "NaN",
+            "ms",
+            "s",
+            "m",
+            " ",
+            "\n",
+            "\t",
+            "\x1b[",
+            "2020-01-01T00:00:00.0000000Z ",
+            "##[section]Starting: Checkout",
+            "##[section]Finishing: Checkout",
+            "cpu family\t: 6",
+            "model\t\t: 85",
+            "[CI_JOB_NAME=",
+            "Job",
+            "]",
+            "AGENT_JOBNAME=",
+            "compiletest suite=",
+            "test result: ok. finished in ",
+            "time: ",
+            "Finished ",
+            " in ",
+            "#1 DONE ",
+            "-Z threads=",
+            "voluntary ctxt switches: ",
+            "running: \"cargo\" \"build\"",
+            "du -sh ",
+            "1.5G\t",
+            "warning: ",
+            "error: ",
+            "\"reason\":\"timing-info\"",
+        ];
+        let cx = cx_with(&["--min-part-secs", "0.5"]);
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut random = || {
+            // xorshift64, which is plenty random for shaking out panics
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        for _ in 0..2000 {
+            let mut bytes = Vec::new();
+            for _ in 0..random() % 64 {
+                match random() % 3 {
+                    0 => bytes.push(random() as u8),
+                    _ => {
+                        let fragment = FRAGMENTS[random() as usize % FRAGMENTS.len()];
+                        bytes.extend_from_slice(fragment.as_bytes());
+                    }
+                }
+            }
+            let contents = lossy_string(bytes);
+            let contents = &*strip_ansi(&contents);
+            cx.extract_timings(contents);
+            cx.extract_test_suites(contents);
+            cx.extract_time_passes(contents);
+            cx.extract_cargo_builds(contents);
+            cx.extract_docker_build(contents);
+            cx.extract_fetch(contents);
+            cx.extract_parallelism(contents);
+            cx.extract_cargo_timings(contents);
+            cx.count_diagnostics(contents);
+            cx.extract_cpu_microarch(contents);
+            let log = Log {
+                job_url: String::new(),
+                contents: contents.to_string(),
+                path: String::new(),
+                result: None,
+                run: 0,
+                parents: Vec::new(),
+                order: None,
+            };
+            let _ = cx.identify_job(&log);
+        }
+    }
+
+    #[test]
+    fn merge_skips_existing_and_corrupt_entries() {
+        let dir = std::env::temp_dir().join(format!("merge-cache-{}", process::id()));
+        let (src, dst) = (dir.join("src"), dir.join("dst"));
+        let gzip = |path: &Path, contents: &str| {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            compress(path, |w| Ok(w.write_all(contents.as_bytes())?)).unwrap();
+        };
+        gzip(&src.join("commits/a.json.gz"), "{}");
+        gzip(&src.join("commits/b.json.gz"), "{}");
+        gzip(&src.join("logs/azure/a-1.gz"), "log");
+        gzip(&dst.join("commits/b.json.gz"), "{}");
+        let corrupt = fs::read(src.join("commits/a.json.gz")).unwrap();
+        fs::write(src.join("logs/azure/a-2.gz"), &corrupt[..corrupt.len() - 4]).unwrap();
+
+        let mut merged = Merged::default();
+        merge_dir(&src.join("commits"), &dst.join("commits"), &mut merged).unwrap();
+        merge_dir(&src.join("logs"), &dst.join("logs"), &mut merged).unwrap();
+        let expected = Merged {
+            copied: 2,
+            existing: 1,
+            corrupt: 1,
+        };
+        assert_eq!(merged, expected);
+        assert!(dst.join("logs/azure/a-1.gz").exists());
+        assert!(!dst.join("logs/azure/a-2.gz").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn merge_unions_skip_lists_and_manifests() {
+        let dir = std::env::temp_dir().join(format!("merge-lists-{}", process::id()));
+        let (src, dst) = (dir.join("src"), dir.join("dst"));
+        let entry = |sha: &str, day: u32| ManifestEntry {
+            sha: sha.to_string(),
+            date: format!("2020-01-0{}T00:00:00+00:00", day),
+            subject: String::new(),
+        };
+        let write_manifest = |cache: &Path, entries: &[ManifestEntry]| {
+            fs::create_dir_all(cache.join("commits")).unwrap();
+            let json = serde_json::to_string(entries).unwrap();
+            fs::write(cache.join(shared::MANIFEST), json).unwrap();
+        };
+        let a = "a".repeat(40);
+        let b = "b".repeat(40);
+        let c = "c".repeat(40);
+        write_manifest(&src, &[entry(&c, 3), entry(&a, 1)]);
+        write_manifest(&dst, &[entry(&b, 2), entry(&a, 1)]);
+        fs::write(src.join("skip-list"), format!("{}\n{}\n", a, b)).unwrap();
+        fs::write(dst.join("skip-list"), format!("{}\n", b)).unwrap();
+
+        let mut cx = cx();
+        cx.cache = dst.clone();
+        cx.merge_cache(&src).unwrap();
+        let manifest: Vec<ManifestEntry> =
+            serde_json::from_slice(&fs::read(dst.join(shared::MANIFEST)).unwrap()).unwrap();
+        let shas = manifest.iter().map(|e| e.sha.as_str()).collect::<Vec<_>>();
+        assert_eq!(shas, [&c, &b, &a]);
+        let skip = fs::read_to_string(dst.join("skip-list")).unwrap();
+        assert_eq!(skip, format!("{}\n{}\n", b, a));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
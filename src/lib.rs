@@ -1,56 +1,883 @@
-use failure::Error;
-use std::collections::BTreeMap;
-use std::io::BufRead;
+use failure::{bail, Error};
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::io::{Read, Write};
 use std::path::Path;
-use std::process::{Command, Stdio};
+use std::process::Command;
 
-#[derive(serde::Serialize, serde::Deserialize, Default)]
+#[derive(serde::Serialize, serde::Deserialize, Default, Clone)]
 pub struct Commit {
     pub jobs: BTreeMap<String, Job>,
+    /// Jobs from any later builds of the same commit, for reducing noise by
+    /// aggregating over several runs.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reruns: Vec<BTreeMap<String, Job>>,
+    /// The CI build's own identifier, for cross-referencing the CI UI.
+    #[serde(default)]
+    pub build_number: Option<String>,
+    #[serde(default)]
+    pub queue_time: Option<String>,
+    #[serde(default)]
+    pub start_time: Option<String>,
+    #[serde(default)]
+    pub finish_time: Option<String>,
+    /// Wall time of the whole build from start to finish, i.e. the total CI
+    /// time for this merge.
+    #[serde(default)]
+    pub build_dur: Option<f64>,
+    /// When set, the parts of each step only record how they differ from
+    /// those of this commit, see `delta_encode`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delta_base: Option<String>,
+    /// `content_hash` of the data of `delta_base` this was encoded against,
+    /// so that decoding against anything else is caught.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delta_base_hash: Option<String>,
 }
 
-#[derive(serde::Serialize, serde::Deserialize)]
+/// The unit of every duration recorded in a `Job`.
+pub const TIMING_UNIT: &str = "seconds";
+
+fn default_unit() -> String {
+    TIMING_UNIT.to_string()
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
 pub struct Job {
     pub url: String,
     pub path: String,
     pub cpu_microarch: Option<String>,
+    /// The CI system's result for this job, e.g. `succeeded` or `failed`.
+    #[serde(default)]
+    pub result: Option<String>,
+    /// How many times this job was retried within the same build.
+    #[serde(default)]
+    pub retries: u32,
+    /// The unit durations in `timings` and `test_suites` were parsed as.
+    #[serde(default = "default_unit")]
+    pub unit: String,
     pub timings: BTreeMap<String, Timing>,
+    /// Time spent in each compiletest suite (`ui`, `codegen`, ...), only
+    /// present for jobs which run the test suite.
+    #[serde(default)]
+    pub test_suites: BTreeMap<String, f64>,
+    /// Time spent in each compiler pass as reported by `-Z time-passes`,
+    /// summed over all crates compiled with it.
+    #[serde(default)]
+    pub passes: BTreeMap<String, Timing>,
+    /// Every cargo invocation's own "Finished ... in <time>" total, in the
+    /// order they appear in the log.
+    #[serde(default)]
+    pub cargo_builds: Vec<CargoBuild>,
+    /// Number of compiler warnings emitted in the job.
+    #[serde(default)]
+    pub warnings: u32,
+    /// Number of compiler errors emitted in the job.
+    #[serde(default)]
+    pub errors: u32,
+    /// Time spent building the job's docker image, if it was built. This is
+    /// wall time when the log has timestamps, otherwise the total time of the
+    /// build's steps, which overstates builds running steps in parallel.
+    #[serde(default)]
+    pub docker_build: Option<f64>,
+    /// Time spent on each unit as recorded by `cargo build --timings`, keyed
+    /// like `syn v1.0.0` with the target appended for non-library units.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub cargo_units: BTreeMap<String, f64>,
+    /// Names of the CI groups (phases, stages, ...) containing this job,
+    /// innermost first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub parents: Vec<String>,
+    /// Position of the job amongst its siblings, as reported by the CI.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub order: Option<u32>,
+    /// How well rustc's parallel front-end used its threads, only present
+    /// for jobs compiling with `-Z threads`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parallel: Option<Parallelism>,
+    /// Time spent checking out the repository and its submodules before
+    /// anything was built, if the log's sections show it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fetch: Option<f64>,
+}
+
+/// Totals of the resource usage bootstrap appends to `[RUSTC-TIMING]` lines,
+/// over all crates which reported it.
+#[derive(serde::Serialize, serde::Deserialize, Default, Clone)]
+pub struct Parallelism {
+    /// The highest `-Z threads` rustc was run with.
+    pub threads: u32,
+    /// Wall time spent compiling the crates.
+    pub wall: f64,
+    /// CPU time, user and system, spent compiling the crates.
+    pub cpu: f64,
+    /// Times rustc gave up the CPU to wait, mostly on locks and other threads.
+    pub voluntary_switches: u64,
+}
+
+impl Parallelism {
+    /// The average number of threads busy while compiling, `cpu / wall`.
+    pub fn effective(&self) -> Option<f64> {
+        if self.wall > 0.0 {
+            Some(self.cpu / self.wall)
+        } else {
+            None
+        }
+    }
 }
 
-#[derive(serde::Serialize, serde::Deserialize, Default)]
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+pub struct CargoBuild {
+    /// The profile built, e.g. `dev` or `release`.
+    pub profile: String,
+    pub dur: f64,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Default, Clone)]
 pub struct Timing {
     pub dur: f64,
     pub parts: BTreeMap<String, f64>,
+    /// How many times each of `parts` was compiled, its value being the sum
+    /// over all of them.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub part_counts: BTreeMap<String, u32>,
+    /// Parts of the base commit's step which this step doesn't have, only
+    /// used by delta-encoded commits.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub removed_parts: Vec<String>,
+    /// Seconds from the start of the job to when this step (first) started.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offset: Option<f64>,
+    /// CPU time spent in user mode, when the source reports it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<f64>,
+    /// CPU time spent in the kernel, when the source reports it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system: Option<f64>,
+    /// The cargo subcommand, e.g. `check`, `build` or `doc`, last run in this
+    /// step, if the log shows its invocation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cargo_command: Option<String>,
+    /// Size in bytes of the build directory when this step finished, if the
+    /// log reports it with `du`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_size: Option<u64>,
 }
 
-pub struct GitCommit {
+/// Format of the log output, selected with `--log-format`.
+#[derive(Debug, serde::Deserialize, Clone, Copy)]
+pub enum LogFormat {
+    Human,
+    Json,
+}
+
+/// Initializes `env_logger`, still filtered through `RUST_LOG`.
+///
+/// With `LogFormat::Json` each log event is written as a single-line JSON
+/// object with `level`, `target`, and `message` keys.
+pub fn init_logger(format: LogFormat) {
+    let mut builder = env_logger::Builder::from_default_env();
+    if let LogFormat::Json = format {
+        builder.format(|buf, record| {
+            let event = serde_json::json!({
+                "level": record.level().to_string(),
+                "target": record.target(),
+                "message": record.args().to_string(),
+            });
+            writeln!(buf, "{}", event)
+        });
+    }
+    builder.init();
+}
+
+/// Returns the base URL of the S3 bucket named by `S3_BUCKET`.
+///
+/// Without an `endpoint` this is AWS's virtual-hosted style URL. A custom
+/// endpoint for S3-compatible stores uses path-style addressing
+/// (`<endpoint>/<bucket>`) unless it contains a `{bucket}` placeholder, in
+/// which case the bucket is substituted there instead.
+pub fn s3_url(endpoint: Option<&str>) -> String {
+    let bucket = env::var("S3_BUCKET").expect("missing environment variable S3_BUCKET");
+    match endpoint {
+        Some(endpoint) if endpoint.contains("{bucket}") => endpoint.replace("{bucket}", &bucket),
+        Some(endpoint) => format!("{}/{}", endpoint.trim_end_matches('/'), bucket),
+        None => format!("https://{}.s3.amazonaws.com", bucket),
+    }
+}
+
+/// Shrinks `commit` by dropping each part of its steps which is identical in
+/// `base`, the data of commit `base_sha`, recording any parts `base` has
+/// which `commit` doesn't in `removed_parts`.
+///
+/// Consecutive commits compile mostly the same crates in mostly the same
+/// time, so this leaves little behind. `delta_decode` reverses it given the
+/// same `base`, which must not itself be delta-encoded.
+pub fn delta_encode(commit: &mut Commit, base_sha: &str, base: &Commit) {
+    for (name, job) in commit.jobs.iter_mut() {
+        let base_job = match base.jobs.get(name) {
+            Some(job) => job,
+            None => continue,
+        };
+        for (step, timing) in job.timings.iter_mut() {
+            let base_timing = match base_job.timings.get(step) {
+                Some(timing) => timing,
+                None => continue,
+            };
+            timing.removed_parts = base_timing
+                .parts
+                .keys()
+                .filter(|k| !timing.parts.contains_key(*k))
+                .cloned()
+                .collect();
+            // Compare bits so that decoding reproduces exactly the same value
+            timing
+                .parts
+                .retain(|k, v| base_timing.parts.get(k).map(|b| b.to_bits()) != Some(v.to_bits()));
+        }
+    }
+    commit.delta_base = Some(base_sha.to_string());
+    commit.delta_base_hash = Some(content_hash(base));
+}
+
+/// Restores the full data of `commit` if it was delta-encoded against
+/// `base`.
+pub fn delta_decode(commit: &mut Commit, base: &Commit) -> Result<(), Error> {
+    if commit.delta_base.is_none() {
+        return Ok(());
+    }
+    if let Some(sha) = &base.delta_base {
+        bail!("delta base is itself delta-encoded against {}", sha);
+    }
+    // Commits encoded before the hash was recorded have to be taken on trust
+    if let Some(hash) = &commit.delta_base_hash {
+        let actual = content_hash(base);
+        if *hash != actual {
+            bail!(
+                "delta base {} has changed since encoding ({} != {})",
+                commit.delta_base.as_deref().unwrap_or(""),
+                actual,
+                hash
+            );
+        }
+    }
+    for (name, job) in commit.jobs.iter_mut() {
+        let base_job = match base.jobs.get(name) {
+            Some(job) => job,
+            None => continue,
+        };
+        for (step, timing) in job.timings.iter_mut() {
+            let base_timing = match base_job.timings.get(step) {
+                Some(timing) => timing,
+                None => continue,
+            };
+            for (k, v) in base_timing.parts.iter() {
+                if !timing.removed_parts.contains(k) {
+                    timing.parts.entry(k.clone()).or_insert(*v);
+                }
+            }
+            timing.removed_parts.clear();
+        }
+    }
+    commit.delta_base = None;
+    commit.delta_base_hash = None;
+    Ok(())
+}
+
+/// A hash of `bytes` which, unlike `DefaultHasher`, is guaranteed to stay the
+/// same across Rust releases and platforms, for anything that's persisted.
+///
+/// This is 64-bit FNV-1a, which is plenty to tell apart the handful of things
+/// hashed here but of course isn't cryptographic.
+pub fn stable_hash(bytes: &[u8]) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    for b in bytes {
+        hash ^= u64::from(*b);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+/// `stable_hash` of `commit` as serialized, in hex.
+pub fn content_hash(commit: &Commit) -> String {
+    let json = serde_json::to_vec(commit).expect("commits always serialize");
+    format!("{:016x}", stable_hash(&json))
+}
+
+/// Decodes a gzip'd `commits/<sha>.json.gz` file as published to S3.
+pub fn decode_commit(raw: impl Read) -> Result<Commit, Error> {
+    let json = std::io::BufReader::new(flate2::read::GzDecoder::new(raw));
+    Ok(serde_json::from_reader(json)?)
+}
+
+/// Downloads the published data for each of `shas` from the bucket at
+/// `s3_url` into `dir`, as `<sha>.json.gz`.
+///
+/// This typically runs right after the data was uploaded, and some S3
+/// endpoints may briefly 404 on newly written objects, so failed downloads are
+/// retried a few times before giving up.
+pub fn download_commits(s3_url: &str, shas: &[&str], dir: &Path) -> Result<(), Error> {
+    std::fs::create_dir_all(dir)?;
+    let mut attempt = 1;
+    loop {
+        let urls = shas
+            .iter()
+            .filter(|sha| !dir.join(sha).with_extension("json.gz").exists())
+            .map(|sha| format!("{}/commits/{}.json.gz", s3_url, sha))
+            .collect::<Vec<_>>();
+        println!("downloading {:#?}", urls);
+        let status = Command::new("curl")
+            .arg("--remote-name-all")
+            .arg("-f")
+            .args(&urls)
+            .current_dir(dir)
+            .status()?;
+        if status.success() {
+            return Ok(());
+        }
+        if attempt == 4 {
+            bail!("failed to download commits: {}", status);
+        }
+        std::thread::sleep(std::time::Duration::from_secs(1 << attempt));
+        attempt += 1;
+    }
+}
+
+/// Path of the manifest within the cache and the bucket, see `ManifestEntry`.
+pub const MANIFEST: &str = "commits/index.json";
+
+/// An entry of the manifest, which lists every published commit newest first
+/// so the site can be built without a git checkout.
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+pub struct ManifestEntry {
     pub sha: String,
     pub date: String,
+    #[serde(default)]
+    pub subject: String,
+}
+
+impl ManifestEntry {
+    pub fn new(commit: &GitCommit) -> ManifestEntry {
+        ManifestEntry {
+            sha: commit.sha.clone(),
+            date: commit.date_raw.clone(),
+            subject: commit.subject.clone(),
+        }
+    }
+
+    /// Recreates the `GitCommit` as best it can, which is without anything
+    /// from the message beyond its subject.
+    pub fn git_commit(&self) -> GitCommit {
+        git_commit(self.sha.clone(), self.date.clone(), &self.subject)
+    }
+}
+
+/// Downloads the manifest from the bucket at `s3_url`, or `None` if there
+/// isn't one yet.
+pub fn download_manifest(s3_url: &str) -> Result<Option<Vec<ManifestEntry>>, Error> {
+    let url = format!("{}/{}", s3_url, MANIFEST);
+    log::debug!("GET: {}", url);
+    // The status code is written after the body to tell a missing manifest
+    // apart from failing to fetch it.
+    let output = Command::new("curl")
+        .arg("-sS")
+        .arg("-w")
+        .arg("%{http_code}")
+        .arg(&url)
+        .output()?;
+    if !output.status.success() {
+        bail!("failed to fetch `{}`: {}", url, output.status);
+    }
+    let (body, status) = output
+        .stdout
+        .split_at(output.stdout.len().saturating_sub(3));
+    match status {
+        b"200" => Ok(Some(serde_json::from_slice(body)?)),
+        // Anything else, including a 403, might just be a failure to read an
+        // existing manifest which mustn't be mistaken for there being none.
+        b"404" => Ok(None),
+        _ => bail!(
+            "failed to fetch `{}`: status {}",
+            url,
+            String::from_utf8_lossy(status)
+        ),
+    }
+}
+
+/// Lists the SHA of every commit published to the bucket at `s3_url`.
+///
+/// This uses S3's `ListObjectsV2` API, so the bucket must allow listing.
+pub fn list_published_commits(s3_url: &str) -> Result<Vec<String>, Error> {
+    let mut ret = Vec::new();
+    let mut token = None::<String>;
+    loop {
+        let mut url = format!("{}/?list-type=2&prefix=commits/", s3_url);
+        if let Some(token) = &token {
+            url.push_str("&continuation-token=");
+            url.push_str(&percent_encode(token));
+        }
+        let xml = String::from_utf8(curl(&url)?)?;
+        for key in xml_values(&xml, "Key") {
+            let sha = key
+                .strip_prefix("commits/")
+                .and_then(|k| k.strip_suffix(".json.gz"));
+            if let Some(sha) = sha {
+                ret.push(sha.to_string());
+            }
+        }
+        if xml_values(&xml, "IsTruncated").first() != Some(&"true") {
+            return Ok(ret);
+        }
+        token = xml_values(&xml, "NextContinuationToken")
+            .first()
+            .map(|s| s.to_string());
+        if token.is_none() {
+            bail!("truncated listing without a continuation token");
+        }
+    }
+}
+
+/// Iterates over all commits published to the bucket at `s3_url`, without
+/// needing a git checkout.
+///
+/// Commits are yielded in the order S3 lists them (by SHA), each one being
+/// downloaded as the iterator advances. Delta-encoded commits are yielded
+/// decoded, downloading their base as needed.
+pub fn published_commits(
+    s3_url: &str,
+) -> Result<impl Iterator<Item = Result<(String, Commit), Error>>, Error> {
+    let shas = list_published_commits(s3_url)?;
+    let s3_url = s3_url.to_string();
+    let download = move |sha: &str| -> Result<Commit, Error> {
+        let raw = curl(&format!("{}/commits/{}.json.gz", s3_url, sha))?;
+        decode_commit(&raw[..])
+    };
+    let mut bases = HashMap::new();
+    Ok(shas.into_iter().map(move |sha| {
+        let mut commit = download(&sha)?;
+        if let Some(base) = commit.delta_base.clone() {
+            if !bases.contains_key(&base) {
+                bases.insert(base.clone(), download(&base)?);
+            }
+            delta_decode(&mut commit, &bases[&base])?;
+        }
+        Ok((sha, commit))
+    }))
+}
+
+fn curl(url: &str) -> Result<Vec<u8>, Error> {
+    log::debug!("GET: {}", url);
+    let output = Command::new("curl").arg("-sSf").arg(url).output()?;
+    if !output.status.success() {
+        bail!("failed to fetch `{}`: {}", url, output.status);
+    }
+    Ok(output.stdout)
 }
 
+/// Crude extraction of the text of every `<tag>` element in `xml`, which is
+/// all that's needed from S3's listing responses.
+fn xml_values<'a>(xml: &'a str, tag: &str) -> Vec<&'a str> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    xml.split(open.as_str())
+        .skip(1)
+        .filter_map(|s| s.split(close.as_str()).next())
+        .collect()
+}
+
+/// Escapes everything in `s` but unreserved characters, for use as a URL
+/// query parameter.
+pub fn percent_encode(s: &str) -> String {
+    let mut ret = String::new();
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                ret.push(b as char)
+            }
+            _ => ret.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    ret
+}
+
+/// Expands a possibly-abbreviated `sha` to the full SHA of a commit in the
+/// cache, looking at both published commits and cached logs.
+///
+/// Full 40-character SHAs are returned as-is, and it's an error for a short
+/// SHA to match no commits or more than one.
+pub fn resolve_sha(cache: &Path, sha: &str) -> Result<String, Error> {
+    if sha.len() == 40 {
+        return Ok(sha.to_string());
+    }
+    let mut matches = std::collections::BTreeSet::new();
+    for dir in ["commits", "logs/azure", "logs/github", "logs/gitlab"].iter() {
+        let dir = cache.join(dir);
+        if !dir.exists() {
+            continue;
+        }
+        for entry in std::fs::read_dir(&dir)? {
+            let name = entry?.file_name();
+            let name = name.to_string_lossy();
+            if name.len() >= 40 && name.starts_with(sha) {
+                matches.insert(name[..40].to_string());
+            }
+        }
+    }
+    let mut iter = matches.into_iter();
+    match (iter.next(), iter.next()) {
+        (Some(full), None) => Ok(full),
+        (None, _) => bail!("no cached commit matches `{}`", sha),
+        (Some(a), Some(b)) => bail!("`{}` is ambiguous, matching {} and {}", sha, a, b),
+    }
+}
+
+pub struct GitCommit {
+    pub sha: String,
+    /// When the commit was authored, or `None` if git printed a date which
+    /// couldn't be parsed.
+    pub date: Option<chrono::DateTime<chrono::FixedOffset>>,
+    /// The date exactly as git printed it, which is what gets written out.
+    pub date_raw: String,
+    /// The first line of the commit's message.
+    pub subject: String,
+    /// The number of the PR bors merged, parsed from the subject.
+    pub pr: Option<u32>,
+    /// Who opened the PR, parsed from the `<user>:<branch>` in the subject.
+    pub author: Option<String>,
+    /// Who approved the PR, parsed from the message of bors' merge commit.
+    pub approved_by: Option<String>,
+    /// The release channel, `nightly`, `beta` or `stable`, which is only
+    /// filled in by `detect_channels` (or some other label standing in for
+    /// it).
+    pub channel: Option<String>,
+}
+
+/// Fills in the `channel` of each of `commits` from the `src/ci/channel`
+/// file in the checkout at that commit, leaving it `None` for commits from
+/// before that file existed.
+pub fn detect_channels(repo: &Path, commits: &mut [GitCommit]) -> Result<(), Error> {
+    use std::io::BufRead;
+    use std::process::Stdio;
+
+    let mut child = Command::new("git")
+        .arg("cat-file")
+        .arg("--batch")
+        .current_dir(repo)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let mut stdin = child.stdin.take().unwrap();
+    let requests = commits
+        .iter()
+        .map(|c| format!("{}:src/ci/channel\n", c.sha))
+        .collect::<String>();
+    // Write from another thread so a full stdout pipe can't deadlock us
+    let writer = std::thread::spawn(move || stdin.write_all(requests.as_bytes()));
+
+    let mut stdout = std::io::BufReader::new(child.stdout.take().unwrap());
+    for commit in commits.iter_mut() {
+        // Each response is `<oid> blob <size>` followed by the contents, or
+        // `<object> missing`
+        let mut header = String::new();
+        stdout.read_line(&mut header)?;
+        let size = match header.trim_end().strip_suffix(" missing") {
+            Some(_) => continue,
+            None => match header.split_whitespace().nth(2).map(|s| s.parse::<usize>()) {
+                Some(Ok(size)) => size,
+                _ => bail!("unexpected `git cat-file` output: {}", header),
+            },
+        };
+        let mut contents = vec![0; size + 1];
+        stdout.read_exact(&mut contents)?;
+        let channel = String::from_utf8_lossy(&contents).trim().to_string();
+        if !channel.is_empty() {
+            commit.channel = Some(channel);
+        }
+    }
+    writer.join().unwrap()?;
+    child.wait()?;
+    Ok(())
+}
+
+/// Parses a date as printed by `git log --pretty=%aI`.
+///
+/// Older versions of git print offsets without a colon, which isn't RFC 3339
+/// but is otherwise the same, so that's accepted too.
+pub fn parse_git_date(
+    raw: &str,
+) -> Result<chrono::DateTime<chrono::FixedOffset>, chrono::ParseError> {
+    chrono::DateTime::parse_from_rfc3339(raw)
+        .or_else(|_| chrono::DateTime::parse_from_str(raw, "%Y-%m-%dT%H:%M:%S%z"))
+}
+
+/// Fills in a `GitCommit` with everything parsed out of its `message`.
+fn git_commit(sha: String, date_raw: String, message: &str) -> GitCommit {
+    let date = match parse_git_date(&date_raw) {
+        Ok(date) => Some(date),
+        Err(e) => {
+            // A git which prints these in some other format would print all
+            // of them that way, so once is plenty.
+            static WARNED: std::sync::Once = std::sync::Once::new();
+            WARNED.call_once(|| {
+                log::warn!(
+                    "failed to parse date `{}` of {}, leaving it and any \
+                     others like it unparsed: {}",
+                    date_raw,
+                    sha,
+                    e
+                );
+            });
+            None
+        }
+    };
+    let message = message.trim_start();
+    let subject = message.lines().next().unwrap_or("");
+    let (pr, author) = parse_merged_pr(subject);
+    GitCommit {
+        sha,
+        date,
+        date_raw,
+        subject: subject.to_string(),
+        pr,
+        author,
+        approved_by: parse_approved_by(message),
+        channel: None,
+    }
+}
+
+/// Finds the approver in a bors merge commit message, either from an
+/// `Approved by: <who>` line or the `r=<who>` of an `Auto merge of` subject.
+fn parse_approved_by(message: &str) -> Option<String> {
+    for line in message.lines() {
+        if let Some(who) = line.trim().strip_prefix("Approved by:") {
+            return Some(who.trim().to_string()).filter(|s| !s.is_empty());
+        }
+    }
+    let subject = message.lines().next()?;
+    if !subject.starts_with("Auto merge of ") {
+        return None;
+    }
+    let pos = subject.rfind(" r=")?;
+    Some(subject[pos + 3..].trim().to_string()).filter(|s| !s.is_empty())
+}
+
+/// Finds the PR number and its author in a bors merge commit's subject, like
+/// `Auto merge of #123 - user:branch, r=reviewer`.
+fn parse_merged_pr(subject: &str) -> (Option<u32>, Option<String>) {
+    let rest = match subject.strip_prefix("Auto merge of #") {
+        Some(rest) => rest,
+        None => return (None, None),
+    };
+    let end = rest
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(rest.len());
+    let pr = rest[..end].parse().ok();
+    let author = rest[end..]
+        .strip_prefix(" - ")
+        .and_then(|branch| Some(&branch[..branch.find(':')?]))
+        .filter(|user| !user.is_empty() && !user.contains(' '))
+        .map(|user| user.to_string());
+    (pr, author)
+}
+
+/// Returns the position of each commit in the first-parent history of `HEAD`,
+/// 0 being `HEAD` itself.
+pub fn first_parent_positions(repo: &Path) -> Result<HashMap<String, usize>, Error> {
+    let output = Command::new("git")
+        .arg("rev-list")
+        .arg("--first-parent")
+        .arg("HEAD")
+        .current_dir(repo)
+        .output()?;
+    if !output.status.success() {
+        bail!(
+            "failed to list first-parent history: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    Ok(String::from_utf8(output.stdout)?
+        .lines()
+        .enumerate()
+        .map(|(i, sha)| (sha.to_string(), i))
+        .collect())
+}
+
+/// Expands a possibly-abbreviated `sha` to the full SHA of a commit in
+/// `repo`, failing if git finds no such commit or more than one.
+pub fn resolve_git_sha(repo: &Path, sha: &str) -> Result<String, Error> {
+    let output = Command::new("git")
+        .arg("rev-parse")
+        .arg("--verify")
+        .arg(format!("{}^{{commit}}", sha))
+        .current_dir(repo)
+        .output()?;
+    if !output.status.success() {
+        bail!(
+            "failed to resolve `{}`: {}",
+            sha,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8(output.stdout)?.trim().to_string())
+}
+
+/// Lists the commits of `repo` newest first, only those by `author` (such as
+/// the merge bot) if it's given.
 pub fn get_git_commits(
     repo: &Path,
+    author: Option<&str>,
 ) -> Result<impl Iterator<Item = Result<GitCommit, Error>>, Error> {
-    let mut child = Command::new("git")
-        .arg("log")
-        .arg("--author=bors")
-        .arg("--pretty=%H %aI")
+    get_git_commits_from(repo, "HEAD", author)
+}
+
+/// Like `get_git_commits`, but the history of `rev` rather than of `HEAD`.
+#[cfg(not(feature = "gitoxide"))]
+pub fn get_git_commits_from(
+    repo: &Path,
+    rev: &str,
+    author: Option<&str>,
+) -> Result<impl Iterator<Item = Result<GitCommit, Error>>, Error> {
+    use std::process::{Command, Stdio};
+
+    // Each commit is its sha and date on one line followed by its message,
+    // with `-z` separating commits by a NUL byte.
+    let mut cmd = Command::new("git");
+    cmd.arg("log").arg("-z");
+    if let Some(author) = author {
+        cmd.arg(format!("--author={}", author));
+    }
+    let mut child = cmd
+        .arg("--pretty=%H %aI%n%B")
+        .arg(rev)
         .current_dir(repo)
         .stdout(Stdio::piped())
         .spawn()?;
-    let mut stdout = std::io::BufReader::new(child.stdout.take().unwrap());
+    let stdout = std::io::BufReader::new(child.stdout.take().unwrap());
+    Ok(git_log_records(stdout))
+}
 
-    Ok(std::iter::repeat(()).filter_map(move |()| {
-        let mut line = String::new();
-        match stdout.read_line(&mut line) {
+/// Parses the NUL-separated records of `git log -z --pretty=%H %aI%n%B`.
+#[cfg(not(feature = "gitoxide"))]
+fn git_log_records(
+    mut log: impl std::io::BufRead,
+) -> impl Iterator<Item = Result<GitCommit, Error>> {
+    std::iter::from_fn(move || {
+        let mut raw = Vec::new();
+        match log.read_until(0, &mut raw) {
             Ok(0) => return None,
             Ok(_) => {}
             Err(e) => return Some(Err(e.into())),
         }
+        let record = String::from_utf8_lossy(&raw);
+        let record = record.trim_end_matches('\0');
+        let (line, message) = record.split_at(record.find('\n').unwrap_or(record.len()));
         let mut parts = line.split_whitespace();
-        Some(Ok(GitCommit {
-            sha: parts.next().unwrap().to_string(),
-            date: parts.next().unwrap().to_string(),
-        }))
+        let (sha, date) = match (parts.next(), parts.next()) {
+            (Some(sha), Some(date)) => (sha, date),
+            _ => {
+                return Some(Err(failure::format_err!(
+                    "malformed `git log` line `{}`, expected a sha and a date",
+                    line
+                )))
+            }
+        };
+        Some(Ok(git_commit(sha.to_string(), date.to_string(), message)))
+    })
+}
+
+#[cfg(feature = "gitoxide")]
+pub fn get_git_commits_from(
+    repo: &Path,
+    rev: &str,
+    author: Option<&str>,
+) -> Result<impl Iterator<Item = Result<GitCommit, Error>>, Error> {
+    use gix::traverse::commit::simple::{CommitTimeOrder, Sorting};
+
+    // Walk newest-first by commit time to match `git log`'s default order.
+    // This walks its own handle to the object database, rather than borrowing
+    // `repo`, so that commits can be yielded as they're walked.
+    let repo = gix::open(repo)?;
+    // Without gitoxide's `revision` feature only `HEAD`, full SHAs and
+    // reference names can be resolved, which covers every caller.
+    let tip = if rev == "HEAD" {
+        repo.head_id()?.detach()
+    } else if let Ok(id) = gix::ObjectId::from_hex(rev.as_bytes()) {
+        id
+    } else {
+        repo.find_reference(rev)?.peel_to_id()?.detach()
+    };
+    let walk = gix::traverse::commit::Simple::new(Some(tip), repo.objects.clone())
+        .sorting(Sorting::ByCommitTime(CommitTimeOrder::NewestFirst))?;
+    let author = author.map(|s| s.to_string());
+    Ok(walk.filter_map(move |info| {
+        let commit = (|| -> Result<Option<GitCommit>, Error> {
+            let commit = repo.find_commit(info?.id)?;
+            let signature = commit.author()?;
+            // `--author` matches against both the name and the email
+            if let Some(author) = &author {
+                if !signature.name.to_string().contains(author.as_str())
+                    && !signature.email.to_string().contains(author.as_str())
+                {
+                    return Ok(None);
+                }
+            }
+            let date = signature
+                .time()?
+                .format(gix::date::time::format::ISO8601_STRICT)?;
+            let message = commit.message_raw()?.to_string();
+            Ok(Some(git_commit(commit.id.to_string(), date, &message)))
+        })();
+        commit.transpose()
     }))
 }
+
+#[cfg(all(test, not(feature = "gitoxide")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn malformed_git_log_lines() {
+        let log = "\
+1111111111111111111111111111111111111111 2020-01-02T00:00:00+00:00
+Auto merge of #2 - bob:fix, r=alice
+\0\
+2222222222222222222222222222222222222222
+Auto merge of #1 - bob:grafted, r=alice
+\0";
+        let mut commits = git_log_records(log.as_bytes());
+        let first = commits.next().unwrap().unwrap();
+        assert_eq!(first.date_raw, "2020-01-02T00:00:00+00:00");
+        assert_eq!(first.date.unwrap().timestamp(), 1577923200);
+        assert_eq!(first.pr, Some(2));
+        let err = commits.next().unwrap().err().unwrap().to_string();
+        assert!(
+            err.contains("2222222222222222222222222222222222222222"),
+            "{}",
+            err
+        );
+        assert!(commits.next().is_none());
+    }
+
+    #[test]
+    fn subjects_keep_their_spaces() {
+        let log = "\
+1111111111111111111111111111111111111111 2020-01-02T00:00:00+00:00
+Rollup of 3  pull requests
+
+Approved by: alice
+\0";
+        let commit = git_log_records(log.as_bytes()).next().unwrap().unwrap();
+        assert_eq!(commit.subject, "Rollup of 3  pull requests");
+        assert_eq!(commit.approved_by.as_deref(), Some("alice"));
+    }
+
+    #[test]
+    fn dates_without_a_colon_in_the_offset() {
+        let commit = git_commit("a".to_string(), "2020-01-02T00:00:00+0100".to_string(), "");
+        assert_eq!(commit.date.unwrap().timestamp(), 1577919600);
+        assert_eq!(commit.date_raw, "2020-01-02T00:00:00+0100");
+
+        let commit = git_commit("a".to_string(), "yesterday".to_string(), "");
+        assert_eq!(commit.date, None);
+        assert_eq!(commit.date_raw, "yesterday");
+    }
+}
@@ -0,0 +1,117 @@
+use shared::Commit;
+
+fn commit(json: serde_json::Value) -> Commit {
+    serde_json::from_value(json).unwrap()
+}
+
+fn job(parts: serde_json::Value) -> serde_json::Value {
+    serde_json::json!({
+        "url": "",
+        "path": "",
+        "cpu_microarch": null,
+        "timings": {
+            "build": { "dur": 100.0, "parts": parts },
+        },
+    })
+}
+
+fn round_trip(base: &Commit, commit: &Commit) -> Commit {
+    let mut encoded = commit.clone();
+    shared::delta_encode(&mut encoded, "base", base);
+    assert_eq!(encoded.delta_base.as_deref(), Some("base"));
+
+    // Make sure what's stored is what's decoded
+    let json = serde_json::to_string(&encoded).unwrap();
+    let mut decoded: Commit = serde_json::from_str(&json).unwrap();
+    shared::delta_decode(&mut decoded, base).unwrap();
+    assert_eq!(
+        serde_json::to_value(&decoded).unwrap(),
+        serde_json::to_value(commit).unwrap()
+    );
+    encoded
+}
+
+#[test]
+fn unchanged_parts_are_dropped() {
+    let base = commit(serde_json::json!({
+        "jobs": { "a": job(serde_json::json!({ "syn": 1.5, "serde": 2.0 })) },
+    }));
+    let encoded = round_trip(&base, &base);
+    let timing = &encoded.jobs["a"].timings["build"];
+    assert!(timing.parts.is_empty());
+    assert!(timing.removed_parts.is_empty());
+}
+
+#[test]
+fn changed_added_and_removed_parts() {
+    let base = commit(serde_json::json!({
+        "jobs": { "a": job(serde_json::json!({ "syn": 1.5, "serde": 2.0, "libc": 0.1 })) },
+    }));
+    let next = commit(serde_json::json!({
+        "jobs": { "a": job(serde_json::json!({ "syn": 1.5, "serde": 2.25, "rand": 0.3 })) },
+    }));
+    let encoded = round_trip(&base, &next);
+    let timing = &encoded.jobs["a"].timings["build"];
+    assert_eq!(timing.parts.keys().collect::<Vec<_>>(), ["rand", "serde"]);
+    assert_eq!(timing.removed_parts, ["libc"]);
+}
+
+#[test]
+fn jobs_and_steps_missing_from_the_base_are_kept_in_full() {
+    let base = commit(serde_json::json!({
+        "jobs": { "a": job(serde_json::json!({ "syn": 1.5 })) },
+    }));
+    let next = commit(serde_json::json!({
+        "jobs": {
+            "a": {
+                "url": "",
+                "path": "",
+                "cpu_microarch": null,
+                "timings": {
+                    "build": { "dur": 1.0, "parts": { "syn": 1.5 } },
+                    "test": { "dur": 1.0, "parts": { "syn": 0.5 } },
+                },
+            },
+            "b": job(serde_json::json!({ "syn": 1.5 })),
+        },
+    }));
+    let encoded = round_trip(&base, &next);
+    assert_eq!(encoded.jobs["a"].timings["test"].parts.len(), 1);
+    assert_eq!(encoded.jobs["b"].timings["build"].parts.len(), 1);
+}
+
+#[test]
+fn chained_deltas_are_rejected() {
+    let mut base = commit(serde_json::json!({
+        "jobs": { "a": job(serde_json::json!({ "syn": 1.5 })) },
+    }));
+    base.delta_base = Some("older".to_string());
+    let mut next = base.clone();
+    next.delta_base = Some("base".to_string());
+    assert!(shared::delta_decode(&mut next, &base).is_err());
+}
+
+#[test]
+fn a_changed_base_is_rejected() {
+    let base = commit(serde_json::json!({
+        "jobs": { "a": job(serde_json::json!({ "syn": 1.5, "serde": 2.0 })) },
+    }));
+    let mut encoded = base.clone();
+    shared::delta_encode(&mut encoded, "base", &base);
+
+    let mut changed = base.clone();
+    changed.build_number = Some("2".to_string());
+    let err = shared::delta_decode(&mut encoded.clone(), &changed).unwrap_err();
+    assert!(err.to_string().contains("has changed"), "{}", err);
+
+    // Commits encoded before the hash was recorded still decode
+    encoded.delta_base_hash = None;
+    shared::delta_decode(&mut encoded, &changed).unwrap();
+    assert_eq!(encoded.jobs["a"].timings["build"].parts.len(), 2);
+}
+
+#[test]
+fn stable_hash_is_fnv1a() {
+    assert_eq!(shared::stable_hash(b""), 0xcbf2_9ce4_8422_2325);
+    assert_eq!(shared::stable_hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+}